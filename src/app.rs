@@ -1,103 +1,437 @@
 use std::{
+    fs,
     io::{stdout, Stdout},
+    ops::Range,
+    path::PathBuf,
     process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
 };
 
-use crossterm::{execute, terminal::LeaveAlternateScreen};
-use hashbrown::{HashMap, HashSet};
-use parking_lot::RwLock;
-
-use crate::{
-    constraints::{Constraint, Solvable, SolvedState},
-    element::{ElementIdentifier, Elements},
+use clue_solver::{
+    element::{ElementIdentifier, ElementState},
+    protocol,
+    server::{Clients, Host},
+    Board, Effect, Game,
+};
+use crossterm::{
+    event::DisableMouseCapture,
+    execute,
+    terminal::{disable_raw_mode, LeaveAlternateScreen},
 };
+use parking_lot::{
+    MappedRwLockReadGuard, MappedRwLockWriteGuard, RwLock, RwLockReadGuard, RwLockWriteGuard,
+};
+
+use serde_json::Value;
+
+use crate::{theme::Theme, ui::Hitbox};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum View {
+    // Every element with its state
+    List,
+    // Cards against players, like a detective notepad
+    Grid,
+}
+
+// Where each game's commands are saved, one file per game
+const GAMES_DIR: &str = "./games";
 
 pub struct App {
-    pub elements: RwLock<Elements>,
-    pub constraints: RwLock<Vec<Constraint>>,
-    pub constraint_cache: RwLock<HashMap<ElementIdentifier, Option<SolvedState>>>,
+    pub tables: RwLock<Vec<Table>>,
+    // Index of the table being shown
+    pub current: RwLock<usize>,
+    // Deck new games start from
+    pub deck: Board,
+    // Devices following along when serving, see `server`
+    pub clients: RwLock<Option<Arc<Clients>>>,
 
     // == UI ==
+    pub input: RwLock<String>,
+    // Span in the input and message of the last parse error
+    pub input_error: RwLock<Option<(Range<usize>, String)>>,
+    pub view: RwLock<View>,
+    pub theme: RwLock<Theme>,
+    pub element_hitboxes: RwLock<Vec<Hitbox>>,
+    // Set by other threads when the game changed, the main loop draws it
+    pub redraw: AtomicBool,
+    pub stdout: Stdout,
+}
 
+// A named game with the console state that goes with it
+pub struct Table {
+    pub name: String,
+    pub game: Game,
+    pub selected_constraint: Option<usize>,
     // (command, error)
-    pub unsolved_constraints: RwLock<HashSet<Constraint>>,
-    pub command_history: RwLock<Vec<(String, Option<String>)>>,
-    pub stdout: Stdout,
+    pub command_history: Vec<(String, Option<String>)>,
+    // Read only view of the game after this many commands
+    pub timeline: Option<(usize, Game)>,
 }
 
 impl App {
-    pub fn new(elements: Elements) -> Self {
+    pub fn new(deck: Board, theme: Theme) -> Self {
+        let mut tables = load_tables(&deck);
+        if tables.is_empty() {
+            tables.push(Table::new("main".to_owned(), Game::new(deck.clone())));
+        }
+
         Self {
-            elements: RwLock::new(elements),
-            constraints: RwLock::new(Vec::new()),
-            constraint_cache: RwLock::new(HashMap::new()),
+            tables: RwLock::new(tables),
+            current: RwLock::new(0),
+            deck,
+            clients: RwLock::new(None),
 
-            unsolved_constraints: RwLock::new(HashSet::new()),
-            command_history: RwLock::new(Vec::new()),
+            input: RwLock::new(String::new()),
+            input_error: RwLock::new(None),
+            view: RwLock::new(View::List),
+            theme: RwLock::new(theme),
+            element_hitboxes: RwLock::new(Vec::new()),
+            redraw: AtomicBool::new(false),
             stdout: stdout(),
         }
     }
 
+    pub fn table(&self) -> MappedRwLockReadGuard<'_, Table> {
+        let current = *self.current.read();
+        RwLockReadGuard::map(self.tables.read(), |x| &x[current])
+    }
+
+    pub fn table_mut(&self) -> MappedRwLockWriteGuard<'_, Table> {
+        let current = *self.current.read();
+        RwLockWriteGuard::map(self.tables.write(), |x| &mut x[current])
+    }
+
+    // The game being shown, which is a past state in timeline mode
+    pub fn game(&self) -> MappedRwLockReadGuard<'_, Game> {
+        MappedRwLockReadGuard::map(self.table(), Table::shown)
+    }
+
+    pub fn game_mut(&self) -> MappedRwLockWriteGuard<'_, Game> {
+        MappedRwLockWriteGuard::map(self.table_mut(), |x| &mut x.game)
+    }
+
+    // Keep the selection on a constraint that still exists and tell anyone following along
     pub fn refresh_constraints(&self) {
-        let mut cache = self.constraint_cache.write();
-        let mut unsolved = self.unsolved_constraints.write();
-        let constraints = self.constraints.read();
-        let elements = self.elements.read();
-        unsolved.clear();
-        cache.clear();
-
-        for constraint in constraints.iter() {
-            match constraint.solve(&elements) {
-                Ok((element, state)) => {
-                    cache.insert(element, Some(state));
-                }
-                Err(Solvable::No | Solvable::AlreadySolved) => {
-                    unsolved.insert(constraint.to_owned());
-                }
-                _ => {}
+        {
+            let mut table = self.table_mut();
+            let len = table.game.board().constraints().len();
+            if table.selected_constraint.map(|x| x >= len).unwrap_or(false) {
+                table.selected_constraint = len.checked_sub(1);
             }
         }
+
+        // The state is built before the clients are locked, and nothing else is held then
+        let clients = self.clients.read().clone();
+        if let Some(clients) = clients {
+            let state = protocol::state(&self.table().game);
+            clients.changed(state);
+        }
     }
 
+    // Run a command typed by the user.
+    // Commands about the terminal are handled here, everything else goes to the game.
     pub fn execute_command(&self, command: String) {
         if command == "exit" {
-            execute!(self.stdout.lock(), LeaveAlternateScreen).unwrap();
-            process::exit(0);
+            self.exit();
         }
 
-        // Todo: move this someware else
-        if command.starts_with('r') {
-            let index = command
-                .chars()
-                .skip(1)
-                .take_while(|x| x.is_ascii_digit())
-                .collect::<String>()
-                .parse::<usize>()
-                .unwrap()
-                .saturating_sub(1);
-
-            let mut constraints = self.constraints.write();
-            if index < constraints.len() {
-                constraints.remove(index);
+        if self.table().timeline.is_some() {
+            let error = self.timeline_command(&command);
+            self.table_mut().command_history.push((command, error));
+            return;
+        }
+
+        if command == "timeline" {
+            self.open_timeline();
+            self.table_mut().command_history.push((command, None));
+            return;
+        }
+
+        let editing = self.game().editing().is_some();
+
+        if command == "view" && !editing {
+            self.toggle_view();
+            self.table_mut().command_history.push((command, None));
+            return;
+        }
+
+        if let Some(name) = command.strip_prefix("theme ").filter(|_| !editing) {
+            let error = match Theme::preset(name.trim()) {
+                Some(x) => {
+                    *self.theme.write() = x;
+                    None
+                }
+                None => Some("Unknown theme".to_owned()),
+            };
+            self.table_mut().command_history.push((command, error));
+            return;
+        }
+
+        if let Some(name) = command.strip_prefix("game ").filter(|_| !editing) {
+            let error = self.switch_game(name.trim());
+            self.table_mut().command_history.push((command, error));
+            return;
+        }
+
+        if let Some(path) = command.strip_prefix("import ").filter(|_| !editing) {
+            let error = self.import(path.trim());
+            self.table_mut().command_history.push((command, error));
+            self.save();
+            return;
+        }
+
+        let result = self.game_mut().execute(&command);
+        let error = match result {
+            Ok(Effect::Editing(index, text)) => {
+                *self.input.write() = text;
+                self.table_mut().selected_constraint = Some(index);
+                None
+            }
+            Ok(Effect::Exported(format, text)) => {
+                let path = command
+                    .split_whitespace()
+                    .nth(2)
+                    .map(|x| x.to_owned())
+                    .unwrap_or_else(|| format!("clue-export.{}", format.extension()));
+                fs::write(&path, text)
+                    .err()
+                    .map(|e| format!("Couldn't write {}: {}", path, e))
             }
+            Ok(Effect::Done | Effect::Applied(_) | Effect::AutoApply(_)) => {
+                if command == "simplify" {
+                    self.table_mut().selected_constraint = None;
+                }
+                None
+            }
+            Err(e) => {
+                // Put the command back in the input line so it can be fixed
+                if e.retry {
+                    *self.input.write() = command.clone();
+                    *self.input_error.write() = e.span.clone();
+                }
+                Some(e.message)
+            }
+        };
+
+        self.table_mut().command_history.push((command, error));
+        self.save();
+    }
 
-            self.command_history.write().push((command, None));
+    // Set an element without going through the command line, shown in the console like a command
+    pub fn set_element_state(&self, id: ElementIdentifier, state: ElementState) {
+        if self.table().timeline.is_some() {
             return;
         }
 
-        let mut elements = self.elements.write();
-        let mut error = elements.process_action(&command);
+        let result = self.game_mut().set_state(&id, state);
+        let command = format!("{}{}", id, state.as_char());
+        self.table_mut()
+            .command_history
+            .push((command, result.err().map(|e| e.message)));
+        self.save();
+    }
+
+    // Step back through the game, starting from the latest command
+    pub fn open_timeline(&self) {
+        let mut table = self.table_mut();
+        let turn = table.game.history().len();
+        table.timeline = Some((turn, table.game.clone()));
+    }
+
+    pub fn close_timeline(&self) -> bool {
+        self.table_mut().timeline.take().is_some()
+    }
+
+    // Move the timeline by `delta` commands
+    pub fn step_timeline(&self, delta: isize) -> bool {
+        let turn = match &self.table().timeline {
+            Some((x, _)) => *x as isize + delta,
+            None => return false,
+        };
+        self.jump_timeline(turn.max(0) as usize);
+        true
+    }
+
+    fn jump_timeline(&self, turn: usize) {
+        let mut table = self.table_mut();
+        let turn = turn.min(table.game.history().len());
+        let game = table.game.at(turn);
+        table.timeline = Some((turn, game));
+    }
+
+    // Only moving around is allowed in the timeline, `N` jumps to after command N
+    fn timeline_command(&self, command: &str) -> Option<String> {
+        match command.trim().parse::<usize>() {
+            Ok(x) => {
+                self.jump_timeline(x);
+                None
+            }
+            Err(_) => {
+                // The console is showing the past, so point at the input instead
+                let message = "The timeline is read only, esc to leave".to_owned();
+                *self.input.write() = command.to_owned();
+                *self.input_error.write() = Some((0..command.chars().count(), message.clone()));
+                Some(message)
+            }
+        }
+    }
+
+    // Show the game called `name`, starting it if there isn't one
+    fn switch_game(&self, name: &str) -> Option<String> {
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|x| x.is_alphanumeric() || x == '-' || x == '_')
+        {
+            return Some("Game names can only use letters, numbers, - and _".to_owned());
+        }
 
-        if error.is_some() {
-            if let Ok(x) = Constraint::parse(&command) {
-                self.constraints.write().push(x);
-                error = None;
+        let mut tables = self.tables.write();
+        let index = match tables.iter().position(|x| x.name == name) {
+            Some(x) => x,
+            None => {
+                tables.push(Table::new(name.to_owned(), Game::new(self.deck.clone())));
+                tables.len() - 1
             }
+        };
+
+        *self.current.write() = index;
+        self.input.write().clear();
+        *self.input_error.write() = None;
+        None
+    }
+
+    // Write the commands that worked so the game can be replayed next time
+    fn save(&self) {
+        let table = self.table();
+        let commands = table
+            .game
+            .history()
+            .iter()
+            .filter(|x| x.1.is_none())
+            .map(|x| format!("{}\n", x.0))
+            .collect::<String>();
+
+        // Saving is best effort, the game carries on without it
+        let _ = fs::create_dir_all(GAMES_DIR);
+        let _ = fs::write(game_path(&table.name), commands);
+    }
+
+    // Replay a transcript file, reporting the first wrong line
+    fn import(&self, path: &str) -> Option<String> {
+        let raw = match fs::read_to_string(path) {
+            Ok(x) => x,
+            Err(e) => return Some(format!("Couldn't read {}: {}", path, e)),
+        };
+
+        match self.game_mut().import_transcript(&raw) {
+            Ok(_) => None,
+            Err(errors) => {
+                let (line, message) = &errors[0];
+                let more = match errors.len() {
+                    1 => String::new(),
+                    x => format!(" (and {} more)", x - 1),
+                };
+                Some(format!("Line {}: {}{}", line, message, more))
+            }
+        }
+    }
+
+    pub fn toggle_view(&self) {
+        let mut view = self.view.write();
+        *view = match *view {
+            View::List => View::Grid,
+            View::Grid => View::List,
+        };
+    }
+
+    pub fn cancel_edit(&self) {
+        if self.game_mut().cancel_edit() {
+            self.input.write().clear();
+        }
+    }
+
+    pub fn exit(&self) -> ! {
+        disable_raw_mode().unwrap();
+        execute!(
+            self.stdout.lock(),
+            DisableMouseCapture,
+            LeaveAlternateScreen
+        )
+        .unwrap();
+        process::exit(0);
+    }
+}
+
+// The app as the server sees it, requests go to the game being shown
+pub struct Remote(pub Arc<App>);
+
+impl Host for Remote {
+    fn request(&self, raw: &str) -> Vec<Value> {
+        let out = protocol::handle_shared_line(&mut self.0.game_mut(), raw);
+        let (events, responses) = out
+            .into_iter()
+            .partition::<Vec<_>, _>(|x| x.get("event").is_some());
+
+        // Changes go out the same way as ones made on the terminal
+        if !events.is_empty() {
+            self.0.save();
+            self.0.refresh_constraints();
+            self.0.redraw.store(true, Ordering::Relaxed);
+        }
+        responses
+    }
+
+    fn state(&self) -> Value {
+        protocol::state(&self.0.table().game)
+    }
+}
+
+impl Table {
+    // The game being shown, which is a past state in timeline mode
+    pub fn shown(&self) -> &Game {
+        match &self.timeline {
+            Some((_, game)) => game,
+            None => &self.game,
         }
+    }
 
-        self.command_history.write().push((
-            command,
-            error.map(|x| x.unwrap_or_else(|| "Error".to_owned())),
-        ));
+    fn new(name: String, game: Game) -> Self {
+        Self {
+            name,
+            game,
+            selected_constraint: None,
+            command_history: Vec::new(),
+            timeline: None,
+        }
     }
 }
+
+fn game_path(name: &str) -> PathBuf {
+    PathBuf::from(GAMES_DIR).join(format!("{}.txt", name))
+}
+
+// Every saved game, replayed onto the deck
+fn load_tables(deck: &Board) -> Vec<Table> {
+    let entries = match fs::read_dir(GAMES_DIR) {
+        Ok(x) => x,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut out = entries
+        .flatten()
+        .map(|x| x.path())
+        .filter(|x| x.extension().map(|x| x == "txt").unwrap_or(false))
+        .filter_map(|path| {
+            let name = path.file_stem()?.to_str()?.to_owned();
+            let raw = fs::read_to_string(&path).ok()?;
+            Some(Table::new(name, Game::replay(deck.clone(), &raw)))
+        })
+        .collect::<Vec<_>>();
+    out.sort_by(|a, b| a.name.cmp(&b.name));
+    out
+}
@@ -0,0 +1,111 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use clue_solver::{
+    simulation::{self, strategy},
+    Board,
+};
+use rand::{rngs::SmallRng, SeedableRng};
+
+// Options for a run of simulated games
+pub struct Options {
+    pub games: usize,
+    // Strategy names, one per player. The first player is the one whose solver is timed
+    pub strategies: Vec<String>,
+    // The same seed plays the same games
+    pub seed: Option<u64>,
+}
+
+// Play the games and print how many turns the first player's solver needed
+// and how each strategy did
+pub fn run(board: Board, options: Options) {
+    let seed = options.seed.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|x| x.as_nanos() as u64)
+            .unwrap_or_default()
+    });
+    let mut rng = SmallRng::seed_from_u64(seed);
+
+    let players = options.strategies.len();
+    let mut turns = Vec::new();
+    let mut wrong = 0;
+    let mut wins = vec![0; players];
+    let mut win_turns = 0;
+    let mut eliminated = vec![0; players];
+    for _ in 0..options.games {
+        // Fresh strategies every game so nothing is remembered between them
+        let mut strategies = options
+            .strategies
+            .iter()
+            .map(|x| strategy::from_name(x).expect("strategy names are checked by main"))
+            .collect::<Vec<_>>();
+        let outcome = simulation::play(&board, &mut strategies, &mut rng);
+
+        if let Some(x) = outcome.solved {
+            turns.push(x);
+            if !outcome.correct {
+                wrong += 1;
+            }
+        }
+        if let Some((player, turn)) = outcome.winner {
+            wins[player] += 1;
+            win_turns += turn + 1;
+        }
+        for player in outcome.eliminated {
+            eliminated[player] += 1;
+        }
+    }
+    turns.sort_unstable();
+
+    println!(
+        "{} games, {} players ({}), seed {}",
+        options.games,
+        players,
+        options.strategies.join(", "),
+        seed
+    );
+
+    let width = options
+        .strategies
+        .iter()
+        .map(|x| x.len())
+        .max()
+        .unwrap_or(0)
+        .max(8);
+    println!("\nPlayer  {:<width$}  Wins  Wrong accusations", "Strategy");
+    for (i, name) in options.strategies.iter().enumerate() {
+        println!(
+            "P{:<6} {:<width$}  {:<5} {}",
+            i + 1,
+            name,
+            wins[i],
+            eliminated[i]
+        );
+    }
+    let won = wins.iter().sum::<usize>();
+    if won > 0 {
+        println!(
+            "Turns to a winning accusation: mean {:.1}",
+            win_turns as f64 / won as f64
+        );
+    }
+
+    println!(
+        "\nP1's solver knew the answer in {} games, wrong answers {}",
+        turns.len(),
+        wrong
+    );
+    if turns.is_empty() {
+        return;
+    }
+
+    let mean = turns.iter().sum::<usize>() as f64 / turns.len() as f64;
+    println!(
+        "Turns to solution: min {}, median {}, mean {:.1}, max {}",
+        turns[0],
+        turns[turns.len() / 2],
+        mean,
+        turns[turns.len() - 1]
+    );
+    println!("Rounds to solution: mean {:.1}", mean / players as f64);
+}
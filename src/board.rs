@@ -0,0 +1,596 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    path::Path,
+};
+
+use hashbrown::{HashMap, HashSet};
+
+use crate::{
+    constraints::{
+        simplify::{self, Simplified},
+        Constraint, Solution, SolvedState,
+    },
+    element::{ElementIdentifier, ElementState, ElementType, Elements, ParseError},
+};
+
+// A deduction that has been applied to the elements, with the constraint it came from
+pub type Derivation = (Constraint, Solution);
+
+// A deck with the facts and constraints known about it.
+// Everything needed to solve a game without a terminal.
+#[derive(Debug, Clone)]
+pub struct Board {
+    elements: Elements,
+    constraints: Vec<Constraint>,
+    // Deductions that have been applied to `elements`
+    derivations: Vec<Derivation>,
+    // Newest last
+    undo_stack: Vec<Undo>,
+}
+
+// Something `undo` can go back on
+#[derive(Debug, Clone)]
+enum Undo {
+    // Element states before an element command
+    Elements(Elements),
+    // Constraints before they were simplified
+    Constraints(Vec<Constraint>),
+    // Both before `players`, which renumbers the holders in each
+    Players(Elements, Vec<Constraint>),
+}
+
+// What the constraints say about the current elements
+#[derive(Debug, Clone, Default)]
+pub struct Deductions {
+    // State each element must be in, None if constraints disagree
+    pub states: HashMap<ElementIdentifier, Option<SolvedState>>,
+    // Elements that constraints disagree on, with the (constraint index, state) of each
+    pub conflicts: HashMap<ElementIdentifier, Vec<(usize, SolvedState)>>,
+    pub solved: HashMap<Constraint, (ElementIdentifier, SolvedState)>,
+    pub unsolved: HashSet<Constraint>,
+    // If a player holds a card, by (card, player), None if constraints disagree
+    pub holders: HashMap<(ElementIdentifier, usize), Option<bool>>,
+}
+
+#[derive(Debug)]
+pub enum BoardError {
+    Parse(ParseError),
+    // An equivalent constraint already exists at this index
+    Duplicate(usize),
+    // No constraint with this index
+    Index,
+}
+
+impl Board {
+    pub fn new(elements: Elements) -> Self {
+        Self {
+            elements,
+            constraints: Vec::new(),
+            derivations: Vec::new(),
+            undo_stack: Vec::new(),
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Option<Self> {
+        Elements::load(path).map(Self::new)
+    }
+
+    pub fn elements(&self) -> &Elements {
+        &self.elements
+    }
+
+    pub fn constraints(&self) -> &[Constraint] {
+        &self.constraints
+    }
+
+    pub fn derivations(&self) -> &[Derivation] {
+        &self.derivations
+    }
+
+    // Run an element command like `w2d` or `mine w2 l4`, see `Elements::process_action`
+    pub fn process_action(&mut self, command: &str) -> Option<Option<String>> {
+        let before = self.elements.clone();
+        let out = self.elements.process_action(command);
+        if out.is_none() {
+            self.undo_stack.push(Undo::Elements(before));
+        }
+        out
+    }
+
+    // Record a fact about an element
+    pub fn set_state(&mut self, id: &ElementIdentifier, state: ElementState) -> bool {
+        self.process_action(&format!("{}{}", id, state.as_char()))
+            .is_none()
+    }
+
+    // Change who is playing, constraints about players who have gone are dropped
+    pub fn set_players(&mut self, players: Vec<String>) {
+        self.undo_stack.push(Undo::Players(
+            self.elements.clone(),
+            self.constraints.clone(),
+        ));
+        let moved = self
+            .elements
+            .players
+            .iter()
+            .map(|x| players.iter().position(|y| y == x))
+            .collect::<Vec<_>>();
+        self.constraints = self
+            .constraints
+            .iter()
+            .filter_map(|x| x.remap_players(|player| moved.get(player).copied().flatten()))
+            .collect();
+        self.elements.set_players(players);
+    }
+
+    // Go back to before the last element command, simplify or change of players
+    pub fn undo(&mut self) -> bool {
+        match self.undo_stack.pop() {
+            Some(Undo::Elements(x)) => self.elements = x,
+            Some(Undo::Constraints(x)) => self.constraints = x,
+            Some(Undo::Players(elements, constraints)) => {
+                self.elements = elements;
+                self.constraints = constraints;
+            }
+            None => return false,
+        }
+        true
+    }
+
+    pub fn parse(&self, raw: &str) -> Result<Constraint, BoardError> {
+        Constraint::parse(raw, &self.elements).map_err(BoardError::Parse)
+    }
+
+    // Add a constraint unless an equivalent one already exists
+    pub fn add_constraint(&mut self, constraint: Constraint) -> Result<usize, BoardError> {
+        if let Some(x) = self.position(&constraint) {
+            return Err(BoardError::Duplicate(x));
+        }
+
+        self.constraints.push(constraint);
+        Ok(self.constraints.len() - 1)
+    }
+
+    // Swap out a constraint, keeping its position
+    pub fn replace_constraint(
+        &mut self,
+        index: usize,
+        constraint: Constraint,
+    ) -> Result<(), BoardError> {
+        match self.position(&constraint) {
+            Some(x) if x != index => return Err(BoardError::Duplicate(x)),
+            _ => {}
+        }
+
+        match self.constraints.get_mut(index) {
+            Some(x) => *x = constraint,
+            None => return Err(BoardError::Index),
+        }
+        Ok(())
+    }
+
+    pub fn remove_constraint(&mut self, index: usize) -> Result<Constraint, BoardError> {
+        if index >= self.constraints.len() {
+            return Err(BoardError::Index);
+        }
+
+        Ok(self.constraints.remove(index))
+    }
+
+    fn position(&self, constraint: &Constraint) -> Option<usize> {
+        self.constraints.iter().position(|x| x == constraint)
+    }
+
+    // Each constraint rewritten against the states the user entered
+    pub fn simplified(&self) -> Vec<Simplified> {
+        simplify::simplify(&self.constraints, &self.elements.without_derived())
+    }
+
+    // Replace the constraints with their simplified forms.
+    // They rely on the states the user entered, so the originals are kept for `undo`
+    // in case one of those turns out to be wrong.
+    pub fn commit_simplified(&mut self) {
+        let simplified = self.simplified();
+        self.undo_stack
+            .push(Undo::Constraints(self.constraints.clone()));
+
+        let mut out = Vec::new();
+        for (constraint, simplified) in self.constraints.drain(..).zip(simplified) {
+            match simplified {
+                Simplified::Satisfied | Simplified::Subsumed(_) => {}
+                Simplified::Reduced(x) => {
+                    out.push(Constraint::from_clause(&x).unwrap_or(constraint))
+                }
+                Simplified::Violated | Simplified::Unchanged => out.push(constraint),
+            }
+        }
+
+        self.constraints = out;
+    }
+
+    // Work out what every constraint says with the current states
+    pub fn deductions(&self) -> Deductions {
+        let mut out = Deductions::default();
+
+        // Keep showing what applied deductions came from, as long as they are still in use
+        for (constraint, x) in self.derivations.iter() {
+            let element = match self.elements.get(&x.element) {
+                Some(element) => element,
+                None => continue,
+            };
+            let state = element.derived && x.state != SolvedState::Any;
+            let holder = x
+                .holder
+                .filter(|(player, _)| element.derived_holders.contains(player));
+            if state {
+                out.states.insert(x.element, Some(x.state));
+            }
+            if let Some((player, owns)) = holder {
+                out.holders.insert((x.element, player), Some(owns));
+            }
+            if state || holder.is_some() {
+                out.solved
+                    .insert(constraint.to_owned(), (x.element, x.state));
+            }
+        }
+
+        // Group deductions by element so disagreeing constraints can be found
+        let mut found = HashMap::<ElementIdentifier, Vec<(usize, SolvedState)>>::new();
+        for (i, constraint) in self.constraints.iter().enumerate() {
+            if out.solved.contains_key(constraint) {
+                continue;
+            }
+
+            match constraint.solve(&self.elements) {
+                Ok(x) => {
+                    found.entry(x.element).or_default().push((i, x.state));
+                    out.solved
+                        .insert(constraint.to_owned(), (x.element, x.state));
+                    if let Some((player, owns)) = x.holder {
+                        out.holders
+                            .entry((x.element, player))
+                            .and_modify(|y| *y = y.filter(|y| *y == owns))
+                            .or_insert(Some(owns));
+                    }
+                }
+                Err(_) => {
+                    out.unsolved.insert(constraint.to_owned());
+                }
+            }
+        }
+
+        // A conflicting element is stored as None
+        for (element, found) in found {
+            let state = SolvedState::combine(found.iter().map(|x| x.1));
+            if state.is_none() {
+                out.conflicts.insert(element, found);
+            }
+            out.states.insert(element, state);
+        }
+
+        out
+    }
+
+    // Commit deductions into the element states as derived facts.
+    // Applying one can make another constraint solvable, so repeat until nothing changes.
+    // Elements that constraints disagree on are left alone.
+    pub fn apply_deductions(&mut self) -> usize {
+        self.clear_derived();
+
+        loop {
+            let solutions = self
+                .constraints
+                .iter()
+                .filter_map(|x| Some((x, x.solve(&self.elements).ok()?)))
+                .collect::<Vec<_>>();
+
+            let mut states = HashMap::<ElementIdentifier, Vec<SolvedState>>::new();
+            let mut holders = HashMap::<(ElementIdentifier, usize), Vec<bool>>::new();
+            for (_, x) in solutions.iter() {
+                states.entry(x.element).or_default().push(x.state);
+                if let Some((player, owns)) = x.holder {
+                    holders.entry((x.element, player)).or_default().push(owns);
+                }
+            }
+
+            let states = states
+                .into_iter()
+                .filter_map(|(element, found)| {
+                    let state = SolvedState::combine(found.into_iter())?;
+                    self.elements
+                        .derive_state(&element, state.as_state()?)
+                        .then_some((element, state))
+                })
+                .collect::<HashMap<_, _>>();
+            let holders = holders
+                .into_iter()
+                .filter_map(|((element, player), found)| {
+                    let owns = found[0];
+                    (found.iter().all(|x| *x == owns)
+                        && self.elements.derive_owner(&element, player, owns))
+                    .then_some(((element, player), owns))
+                })
+                .collect::<HashMap<_, _>>();
+
+            if states.is_empty() && holders.is_empty() {
+                break;
+            }
+
+            // Every constraint that agrees with an applied fact is kept as where it came from
+            self.derivations.extend(
+                solutions
+                    .into_iter()
+                    .filter(|(_, x)| {
+                        states.get(&x.element) == Some(&x.state)
+                            || x.holder.is_some_and(|(player, owns)| {
+                                holders.get(&(x.element, player)) == Some(&owns)
+                            })
+                    })
+                    .map(|(constraint, x)| (constraint.to_owned(), x)),
+            );
+        }
+
+        self.elements
+            .ids()
+            .iter()
+            .filter_map(|x| self.elements.get(x))
+            .filter(|x| x.derived || !x.derived_holders.is_empty())
+            .count()
+    }
+
+    // Work the applied deductions out again without adding new ones,
+    // dropping those that no longer follow from their constraint.
+    // One can rely on another, so repeat until nothing changes.
+    pub fn recheck_derivations(&mut self) {
+        let mut left = std::mem::take(&mut self.derivations);
+        self.elements.clear_derived();
+
+        loop {
+            // Checked against the same states so constraints that agree are all kept
+            let (holds, rest) = left.into_iter().partition::<Vec<_>, _>(|(constraint, x)| {
+                self.constraints.contains(constraint)
+                    && constraint.solve(&self.elements).ok() == Some(*x)
+            });
+            if holds.is_empty() {
+                break;
+            }
+
+            for (_, x) in holds.iter() {
+                if let Some(state) = x.state.as_state() {
+                    self.elements.derive_state(&x.element, state);
+                }
+                if let Some((player, owns)) = x.holder {
+                    self.elements.derive_owner(&x.element, player, owns);
+                }
+            }
+            self.derivations.extend(holds);
+            left = rest;
+        }
+    }
+
+    // Chance of each element being in the envelope, counting every
+    // location, person and weapon combination that fits what is known as equally likely.
+    // Constraints about who holds what only need to be possible for a combination to fit.
+    // Empty if nothing fits.
+    pub fn probabilities(&self) -> HashMap<ElementIdentifier, f64> {
+        let ids = self.elements.ids();
+        let candidates = |element_type| {
+            ids.iter()
+                .filter(|x| x.element_type == element_type)
+                .filter(|x| self.elements.get_state(x) != Some(ElementState::Dismissed))
+                .copied()
+                .collect::<Vec<_>>()
+        };
+        let (locations, people, weapons) = (
+            candidates(ElementType::Location),
+            candidates(ElementType::Person),
+            candidates(ElementType::Weapon),
+        );
+
+        let mut counts = HashMap::<ElementIdentifier, usize>::new();
+        let mut total = 0;
+        let mut elements = self.elements.clone();
+        for location in &locations {
+            for person in &people {
+                for weapon in &weapons {
+                    let envelope = [location, person, weapon];
+                    for id in ids.iter() {
+                        let state = match envelope.contains(&id) {
+                            true => ElementState::Confirmed,
+                            false => ElementState::Dismissed,
+                        };
+                        elements.set_state(id, state);
+                    }
+
+                    // A confirmed element has to be the one in the envelope
+                    let fits = ids.iter().all(|x| {
+                        self.elements.get_state(x) != Some(ElementState::Confirmed)
+                            || envelope.contains(&x)
+                    }) && self
+                        .constraints
+                        .iter()
+                        .all(|x| x.outcome(&elements) != Some(false));
+
+                    if fits {
+                        total += 1;
+                        for id in envelope {
+                            *counts.entry(*id).or_default() += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        if total == 0 {
+            return HashMap::new();
+        }
+
+        ids.into_iter()
+            .map(|x| {
+                let count = counts.get(&x).copied().unwrap_or(0);
+                (x, count as f64 / total as f64)
+            })
+            .collect()
+    }
+
+    // Forget every applied deduction
+    pub fn clear_derived(&mut self) {
+        self.elements.clear_derived();
+        self.derivations.clear();
+    }
+}
+
+impl Display for BoardError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Parse(x) => write!(f, "{}", x),
+            Self::Duplicate(x) => write!(f, "Duplicate of constraint #{}", x + 1),
+            Self::Index => write!(f, "Invalid constraint index"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::element::ProcesResult;
+
+    fn board() -> Board {
+        Board::new(Elements::numbered(6))
+    }
+
+    fn id(board: &Board, raw: &str) -> ElementIdentifier {
+        board.elements().parse_id(raw).unwrap()
+    }
+
+    fn states(board: &Board, raw: &[&str]) -> Vec<ElementState> {
+        raw.iter()
+            .map(|x| board.elements().get_state(&id(board, x)).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn batches_are_one_step() {
+        use ElementState::*;
+        let mut board = board();
+        assert!(board.process_action("w2,5d l1-3c").is_none());
+        assert_eq!(
+            states(&board, &["w2", "w3", "w5", "l1", "l2", "l3", "l4"]),
+            [Dismissed, Unknown, Dismissed, Confirmed, Confirmed, Confirmed, Unknown]
+        );
+        assert!(board.process_action("mine p1 w6").is_none());
+        assert_eq!(states(&board, &["p1", "w6"]), [Dismissed, Dismissed]);
+
+        // Nothing changes when any part is wrong
+        assert_eq!(
+            board.process_action("w1d w9d"),
+            Some(Some(ProcesResult::IndexRange(6).to_string()))
+        );
+        assert_eq!(
+            board.process_action("l4d l3-1c"),
+            Some(Some(ProcesResult::Index.to_string()))
+        );
+        assert_eq!(
+            board.process_action("mine p2 q1"),
+            Some(Some("Invalid element 'q1d'".to_owned()))
+        );
+        assert_eq!(states(&board, &["w1", "l4", "p2"]), [Unknown; 3]);
+        // Left for the constraint parser to report
+        assert_eq!(board.process_action("w1 | w2"), Some(None));
+
+        assert!(board.undo());
+        assert_eq!(
+            states(&board, &["p1", "w6", "w2"]),
+            [Unknown, Unknown, Dismissed]
+        );
+        assert!(board.undo());
+        assert_eq!(states(&board, &["w2", "l1"]), [Unknown, Unknown]);
+        assert!(!board.undo());
+    }
+
+    #[test]
+    fn simplifying_can_be_undone() {
+        let mut board = board();
+        for raw in [
+            "w1 | w2 | w3 | w4",
+            "w1 | w2 | w3",
+            "w1 | w2",
+            "l1 | l2 | l3",
+            "p1 | p2",
+        ] {
+            let constraint = board.parse(raw).unwrap();
+            board.add_constraint(constraint).unwrap();
+        }
+        assert!(board.process_action("l3d p1c").is_none());
+        let text = |board: &Board| {
+            board
+                .constraints()
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>()
+        };
+        let before = text(&board);
+
+        board.commit_simplified();
+        assert_eq!(text(&board), ["w1 | w2", "l1 | l2"]);
+
+        // The states come back on their own undo step
+        assert!(board.undo());
+        assert_eq!(text(&board), before);
+        assert!(board.undo());
+        assert_eq!(text(&board), before);
+        assert_eq!(states(&board, &["l3", "p1"]), [ElementState::Unknown; 2]);
+    }
+
+    #[test]
+    fn nothing_known_is_uniform() {
+        let probabilities = board().probabilities();
+        assert_eq!(probabilities.len(), 18);
+        for (id, chance) in probabilities {
+            assert!((chance - 1.0 / 6.0).abs() < 1e-9, "{} is {}", id, chance);
+        }
+    }
+
+    #[test]
+    fn determined_board_is_certain() {
+        let mut board = board();
+        for command in ["l1c", "p2c", "w3c"] {
+            assert!(board.process_action(command).is_none());
+        }
+
+        let probabilities = board.probabilities();
+        for x in board.elements().ids() {
+            let expected = match ["l1", "p2", "w3"].contains(&x.to_string().as_str()) {
+                true => 1.0,
+                false => 0.0,
+            };
+            assert_eq!(probabilities[&x], expected, "{}", x);
+        }
+    }
+
+    #[test]
+    fn constraints_narrow_the_chances() {
+        let mut board = board();
+        let constraint = board.parse("w1 | w2").unwrap();
+        board.add_constraint(constraint).unwrap();
+        assert!(board.process_action("w3d").is_none());
+
+        let probabilities = board.probabilities();
+        assert_eq!(probabilities[&id(&board, "w1")], 0.5);
+        assert_eq!(probabilities[&id(&board, "w2")], 0.5);
+        assert_eq!(probabilities[&id(&board, "w3")], 0.0);
+        assert_eq!(probabilities[&id(&board, "w4")], 0.0);
+        assert!((probabilities[&id(&board, "l1")] - 1.0 / 6.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn contradiction_has_no_chances() {
+        let mut board = board();
+        let constraint = board.parse("w1 ^ w2").unwrap();
+        board.add_constraint(constraint).unwrap();
+        assert!(board.process_action("w1d").is_none());
+        assert!(board.process_action("w2d").is_none());
+
+        assert!(board.probabilities().is_empty());
+    }
+}
@@ -1,74 +1,209 @@
-use std::{fmt::Display, ops::Deref};
-
-use crate::element::{ElementIdentifier, ElementState, ElementType, Elements, ProcesResult};
+use std::{
+    cmp::Ordering,
+    fmt::Display,
+    iter::Peekable,
+    ops::{Deref, Range},
+    vec::IntoIter,
+};
+
+use crate::element::{
+    ElementIdentifier, ElementState, ElementType, Elements, Knowledge, ParseError, ProcesResult,
+};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 // A token garented to be a tree
 pub struct Constraint(Token);
 
+#[derive(Debug)]
 pub enum Solvable {
-    Yes(ElementIdentifier),
+    // The element or holding that is left to find
+    Yes(Token),
     AlreadySolved,
     No,
+    // Can't be true whatever the unknown element is
+    Contradiction,
 }
 
 impl Constraint {
     // Parse boolean expressions like:
     // w1 | l3 | p5
-    pub fn parse(raw: &str) -> Result<Self, ProcesResult> {
-        let tokens = tokenize::tokenize(raw)?;
-        let tree = tree::parse(tokens)?;
+    // (p2 -> w1) <-> l3 ^ l4
+    // An element is true when its card is in the envelope,
+    // `w1@2` is true when player 2 holds it
+    pub fn parse(raw: &str, elements: &Elements) -> Result<Self, ParseError> {
+        let tokens = tokenize::tokenize(raw, elements)?;
+        let tree = tree::parse(tokens, raw.chars().count())?;
         Ok(tree)
     }
 
-    // Walk the tree and make sure no more than 1 element is unknown
-    // if returns None then there is more than 1 unknown element
-    // if returns Some then there is 1 unknown element
+    // Walk the tree and make sure no more than 1 element or holding is unknown
     pub fn solveable(&self, elements: &Elements) -> Solvable {
-        let mut missing = None;
+        match &self.unknowns(elements)[..] {
+            [] => Solvable::AlreadySolved,
+            [x] => Solvable::Yes(x.clone()),
+            _ => Solvable::No,
+        }
+    }
 
-        for i in self.flatten_tree() {
-            if let Token::Element(id) = i {
-                if elements.get_state(id) == ElementState::Unknown {
-                    if missing.is_some() {
-                        return Solvable::No;
-                    }
-                    missing = Some(id);
+    // Elements and holdings that aren't known yet, sorted without repeats
+    fn unknowns(&self, elements: &Elements) -> Vec<Token> {
+        let mut out = self
+            .flatten_tree()
+            .into_iter()
+            .filter(|x| match x {
+                Token::Element(id) => elements.get_state(id) == Some(ElementState::Unknown),
+                Token::Held(id, player) => {
+                    elements.player_knowledge(id, *player) == Knowledge::Unknown
                 }
-            }
-        }
+                _ => false,
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        out.sort();
+        out.dedup();
+        out
+    }
 
-        match missing {
-            Some(x) => Solvable::Yes(*x),
-            None => Solvable::AlreadySolved,
+    // Try out a value for an unknown element or holding
+    fn assign(elements: &mut Elements, token: &Token, value: bool) {
+        match token {
+            Token::Element(id) => elements.set_state(
+                id,
+                match value {
+                    true => ElementState::Confirmed,
+                    false => ElementState::Dismissed,
+                },
+            ),
+            Token::Held(id, player) => elements.set_owner(id, *player, value),
+            _ => {}
         }
     }
 
-    // Return the element it has info for and its solved state
-    pub fn solve(&self, elements: &Elements) -> Result<(ElementIdentifier, SolvedState), Solvable> {
+    // What the constraint says about its one unknown element or holding
+    pub fn solve(&self, elements: &Elements) -> Result<Solution, Solvable> {
         let solve_for = match self.solveable(elements) {
             Solvable::Yes(x) => x,
             x => return Err(x),
         };
 
-        const TEST_STATES: [ElementState; 2] = [ElementState::Confirmed, ElementState::Dismissed];
         let mut result = [false; 2];
-
-        for i in 0..TEST_STATES.len() {
+        for (i, value) in [true, false].into_iter().enumerate() {
             let mut elements = elements.clone();
-            elements.set_state(&solve_for, TEST_STATES[i]);
+            Self::assign(&mut elements, &solve_for, value);
             result[i] = Self::_evaluate(&self.0, &elements);
         }
 
-        Ok((
-            solve_for,
-            match result {
-                [true, false] => SolvedState::Confirmed,
-                [false, true] => SolvedState::Dismissed,
-                [true, true] => SolvedState::Any,
-                _ => unreachable!(),
-            },
-        ))
+        let state = match result {
+            [true, false] => SolvedState::Confirmed,
+            [false, true] => SolvedState::Dismissed,
+            [true, true] => SolvedState::Any,
+            [false, false] => return Err(Solvable::Contradiction),
+        };
+
+        match solve_for {
+            Token::Element(element) => Ok(Solution {
+                element,
+                state,
+                holder: None,
+            }),
+            // A held card can't be in the envelope, one that isn't held could be anywhere
+            Token::Held(element, player) => Ok(Solution {
+                element,
+                state: match state {
+                    SolvedState::Confirmed => SolvedState::Dismissed,
+                    _ => SolvedState::Any,
+                },
+                holder: state
+                    .as_state()
+                    .map(|x| (player, x == ElementState::Confirmed)),
+            }),
+            _ => unreachable!(),
+        }
+    }
+
+    // Build `a | b | c` from a list of elements, needs at least two
+    pub fn from_clause(elements: &[ElementIdentifier]) -> Option<Self> {
+        if elements.len() < 2 {
+            return None;
+        }
+
+        let mut tokens = elements.iter().map(|x| Token::Element(*x));
+        let first = tokens.next()?;
+        Some(Constraint(tokens.fold(first, |acc, x| {
+            Token::Tree(Ops::Or, Box::new(acc), Box::new(x))
+        })))
+    }
+
+    // The elements of the constraint if it only uses `|`
+    pub fn clause(&self) -> Option<Vec<ElementIdentifier>> {
+        let mut out = Vec::new();
+        for i in self.flatten_tree() {
+            match i {
+                Token::Element(id) => out.push(*id),
+                _ => return None,
+            }
+        }
+
+        if !self.only_op(Ops::Or) {
+            return None;
+        }
+
+        out.sort();
+        out.dedup();
+        Some(out)
+    }
+
+    // Some(x) if the constraint is x whatever the unknown elements and holdings turn out to be
+    pub fn outcome(&self, elements: &Elements) -> Option<bool> {
+        let unknown = self.unknowns(elements);
+
+        // Too many to try every assignment
+        if unknown.len() > 12 {
+            return None;
+        }
+
+        let mut seen = [false; 2];
+        for assignment in 0..1usize << unknown.len() {
+            // Holdings change element states, so start from the real ones each time.
+            // Elements sort first so holdings are set after them
+            let mut elements = elements.clone();
+            for (i, token) in unknown.iter().enumerate() {
+                Self::assign(&mut elements, token, assignment >> i & 1 == 1);
+            }
+
+            seen[Self::_evaluate(&self.0, &elements) as usize] = true;
+            if seen == [true; 2] {
+                return None;
+            }
+        }
+
+        Some(seen[1])
+    }
+
+    // The same constraint with players moved, None if it mentions one that has gone
+    pub fn remap_players(&self, remap: impl Fn(usize) -> Option<usize>) -> Option<Self> {
+        fn walk(token: &Token, remap: &dyn Fn(usize) -> Option<usize>) -> Option<Token> {
+            Some(match token {
+                Token::Held(id, player) => Token::Held(*id, remap(*player)?),
+                Token::Tree(op, a, b) => {
+                    Token::Tree(*op, Box::new(walk(a, remap)?), Box::new(walk(b, remap)?))
+                }
+                x => x.clone(),
+            })
+        }
+
+        walk(&self.0, &remap).map(|x| Constraint(tree::canonicalize(x)))
+    }
+
+    fn only_op(&self, op: Ops) -> bool {
+        fn walk(token: &Token, op: Ops) -> bool {
+            match token {
+                Token::Tree(x, a, b) => *x == op && walk(a, op) && walk(b, op),
+                _ => true,
+            }
+        }
+
+        walk(&self.0, op)
     }
 
     // true -> Confirmed
@@ -81,104 +216,234 @@ impl Constraint {
 
                 match op {
                     Ops::Or => a || b,
+                    Ops::Xor => a != b,
+                    Ops::Implies => !a || b,
+                    Ops::Iff => a == b,
                 }
             }
             Token::Element(id) => {
-                let state = elements.get_state(id);
-                match state {
-                    ElementState::Confirmed => true,
-                    ElementState::Dismissed => false,
-                    ElementState::Unknown => unreachable!(),
+                match elements.get_state(id) {
+                    Some(ElementState::Confirmed) => true,
+                    Some(ElementState::Dismissed) => false,
+                    Some(ElementState::Unknown) => unreachable!(),
+                    // A card that isn't in the deck can't be in the envelope
+                    None => false,
                 }
             }
+            Token::Held(id, player) => match elements.player_knowledge(id, *player) {
+                Knowledge::Known(x) | Knowledge::Deduced(x) => x,
+                Knowledge::Unknown => unreachable!(),
+            },
             _ => unreachable!(),
         }
     }
 }
 
-#[derive(Debug)]
+// What a solved constraint says about an element
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Solution {
+    pub element: ElementIdentifier,
+    // If the card is in the envelope
+    pub state: SolvedState,
+    // For a holding, the player and if they hold the card
+    pub holder: Option<(usize, bool)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SolvedState {
     Confirmed,
     Dismissed,
     Any,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+impl SolvedState {
+    // Merge what several constraints found for the same element.
+    // Returns None if they disagree.
+    pub fn combine(states: impl Iterator<Item = SolvedState>) -> Option<SolvedState> {
+        let mut out = SolvedState::Any;
+        for state in states {
+            out = match (out, state) {
+                (x, SolvedState::Any) | (SolvedState::Any, x) => x,
+                (a, b) if a == b => a,
+                _ => return None,
+            };
+        }
+
+        Some(out)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SolvedState::Confirmed => "confirmed",
+            SolvedState::Dismissed => "dismissed",
+            SolvedState::Any => "any",
+        }
+    }
+
+    // The state to apply to the element, Any gives no information
+    pub fn as_state(&self) -> Option<ElementState> {
+        match self {
+            SolvedState::Confirmed => Some(ElementState::Confirmed),
+            SolvedState::Dismissed => Some(ElementState::Dismissed),
+            SolvedState::Any => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Token {
     Op(Ops),
     Element(ElementIdentifier),
+    // A player (by index) holds the card
+    Held(ElementIdentifier, usize),
     Tree(Ops, Box<Token>, Box<Token>),
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+// In order of binding strength, loosest last
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Ops {
     Or,
+    Xor,
+    Implies,
+    Iff,
+}
+
+impl Ops {
+    fn precedence(&self) -> u8 {
+        match self {
+            Ops::Or => 4,
+            Ops::Xor => 3,
+            Ops::Implies => 2,
+            Ops::Iff => 1,
+        }
+    }
+
+    // `a -> b -> c` is `a -> (b -> c)`, everything else groups from the left
+    fn right_associative(&self) -> bool {
+        matches!(self, Ops::Implies)
+    }
+
+    // The order of the operands doesnt matter, even when chained
+    fn commutative(&self) -> bool {
+        !matches!(self, Ops::Implies)
+    }
 }
 mod tokenize {
     use super::*;
 
-    struct TokenizeContext {
-        out: Vec<Token>,
-        working: String,
+    pub type Spanned = (Lexeme, Range<usize>);
+
+    #[derive(Debug, Clone)]
+    pub enum Lexeme {
+        Token(Token),
+        Open,
+        Close,
     }
 
-    pub fn tokenize(raw: &str) -> Result<Vec<Token>, ProcesResult> {
-        let mut ctx = TokenizeContext::new();
+    // Spans are in characters, not bytes
+    pub fn tokenize(raw: &str, elements: &Elements) -> Result<Vec<Spanned>, ParseError> {
+        let chars = raw.chars().collect::<Vec<_>>();
+        let mut out = Vec::new();
+        let mut i = 0;
+
+        while i < chars.len() {
+            match chars[i] {
+                x if x.is_whitespace() => i += 1,
+                x if x.is_alphanumeric() => {
+                    let start = i;
+                    while i < chars.len() && chars[i].is_alphanumeric() {
+                        i += 1;
+                    }
 
-        for i in raw.chars() {
-            match i {
-                x if x.is_whitespace() => continue,
-                '|' => ctx.operator(Ops::Or)?,
-                x => ctx.working.push(x),
+                    let word = chars[start..i].iter().collect::<String>();
+                    let id = element(&word, start, elements)?;
+
+                    // `w1@2`, player 2 holds w1
+                    if chars.get(i) == Some(&'@') {
+                        let digits = i + 1;
+                        i = digits;
+                        while i < chars.len() && chars[i].is_ascii_digit() {
+                            i += 1;
+                        }
+
+                        let player = chars[digits..i].iter().collect::<String>();
+                        let player = match player.parse::<usize>() {
+                            Ok(x) if (1..=elements.players.len()).contains(&x) => x - 1,
+                            _ => return Err(ParseError::new(ProcesResult::Player, digits - 1..i)),
+                        };
+                        out.push((Lexeme::Token(Token::Held(id, player)), start..i));
+                        continue;
+                    }
+
+                    out.push((Lexeme::Token(Token::Element(id)), start..i));
+                }
+                '(' => {
+                    out.push((Lexeme::Open, i..i + 1));
+                    i += 1;
+                }
+                ')' => {
+                    out.push((Lexeme::Close, i..i + 1));
+                    i += 1;
+                }
+                x => match operator(&chars[i..]) {
+                    Some((op, len)) => {
+                        out.push((Lexeme::Token(Token::Op(op)), i..i + len));
+                        i += len;
+                    }
+                    None => return Err(ParseError::new(ProcesResult::Character(x), i..i + 1)),
+                },
             }
         }
 
-        ctx.flush()?;
-        Ok(ctx.out)
+        Ok(out)
     }
 
-    impl TokenizeContext {
-        fn new() -> Self {
-            Self {
-                out: Vec::new(),
-                working: String::new(),
-            }
-        }
-
-        fn flush(&mut self) -> Result<(), ProcesResult> {
-            if self.working.is_empty() {
-                return Ok(());
-            }
+    // The operator at the start of `chars` and how many characters it takes up
+    fn operator(chars: &[char]) -> Option<(Ops, usize)> {
+        const OPERATORS: [(&str, Ops); 4] = [
+            ("<->", Ops::Iff),
+            ("->", Ops::Implies),
+            ("|", Ops::Or),
+            ("^", Ops::Xor),
+        ];
+
+        OPERATORS.iter().find_map(|(text, op)| {
+            let len = text.chars().count();
+            (chars.len() >= len && chars[..len].iter().copied().eq(text.chars()))
+                .then_some((*op, len))
+        })
+    }
 
-            let mut chars = self.working.chars();
-            let element_type = match chars.next() {
-                Some('w') => ElementType::Weapon,
-                Some('l') => ElementType::Location,
-                Some('p') => ElementType::Person,
-                _ => return Err(ProcesResult::Section),
-            };
+    // Parse something like `w3`, checking it exists in the deck
+    fn element(
+        word: &str,
+        start: usize,
+        elements: &Elements,
+    ) -> Result<ElementIdentifier, ParseError> {
+        let end = start + word.chars().count();
+        let mut chars = word.chars();
+        let element_type = match chars.next().and_then(ElementType::from_char) {
+            Some(x) => x,
+            None => return Err(ParseError::new(ProcesResult::Section, start..end)),
+        };
 
-            let index = chars
-                .take_while(|x| x.is_ascii_digit())
-                .collect::<String>()
-                .parse::<usize>()
-                .ok()
-                .map(|x| x.saturating_sub(1))
-                .ok_or(ProcesResult::Index)?;
+        let index = match chars.as_str().parse::<usize>() {
+            Ok(x) if x > 0 => x - 1,
+            _ => return Err(ParseError::new(ProcesResult::Index, start + 1..end)),
+        };
 
-            self.working.clear();
-            self.out.push(Token::Element(ElementIdentifier {
-                element_type,
-                index,
-            }));
-            Ok(())
+        let count = elements.section(element_type).len();
+        if index >= count {
+            return Err(ParseError::new(
+                ProcesResult::IndexRange(count),
+                start + 1..end,
+            ));
         }
 
-        fn operator(&mut self, op: Ops) -> Result<(), ProcesResult> {
-            self.flush()?;
-            self.out.push(Token::Op(op));
-            Ok(())
-        }
+        Ok(ElementIdentifier {
+            element_type,
+            index,
+        })
     }
 
     impl Token {
@@ -196,42 +461,142 @@ mod tokenize {
 }
 
 mod tree {
-    use super::*;
+    use super::{
+        tokenize::{Lexeme, Spanned},
+        *,
+    };
+
+    pub fn parse(tokens: Vec<Spanned>, len: usize) -> Result<Constraint, ParseError> {
+        if tokens.is_empty() {
+            return Err(ParseError::new(ProcesResult::Constraint, 0..len));
+        }
+
+        let mut parser = Parser {
+            tokens: tokens.into_iter().peekable(),
+            previous: 0..0,
+        };
+        let tree = parser.expression(0)?;
 
-    pub fn parse(mut tokens: Vec<Token>) -> Result<Constraint, ProcesResult> {
-        if tokens.len() <= 1 {
-            return Err(ProcesResult::Constraint);
+        match parser.tokens.next() {
+            Some((Lexeme::Close, span)) => {
+                return Err(ParseError::new(ProcesResult::Character(')'), span))
+            }
+            // Two elements in a row
+            Some((_, span)) => return Err(ParseError::new(ProcesResult::Operator, span)),
+            None => {}
         }
 
-        while tokens.len() > 1 {
-            let mut i = 0;
+        match canonicalize(tree) {
+            x @ Token::Tree(..) => Ok(Constraint(x)),
+            _ => Err(ParseError::new(ProcesResult::Trivial, 0..len)),
+        }
+    }
+
+    struct Parser {
+        tokens: Peekable<IntoIter<Spanned>>,
+        // Span of the last token taken, used to point at dangling operators
+        previous: Range<usize>,
+    }
 
-            while i < tokens.len() {
-                if let Token::Op(e) = tokens[i] {
-                    let left = safe_remove(&mut tokens, i as isize - 1)?;
-                    let right = safe_remove(&mut tokens, i as isize)?;
+    impl Parser {
+        fn next(&mut self) -> Option<Spanned> {
+            let next = self.tokens.next()?;
+            self.previous = next.1.clone();
+            Some(next)
+        }
 
-                    tokens[i - 1] = Token::Tree(e, Box::new(left), Box::new(right));
+        // Precedence climbing, only takes operators binding at least as tight as `min`
+        fn expression(&mut self, min: u8) -> Result<Token, ParseError> {
+            let mut left = self.operand()?;
+
+            while let Some((Lexeme::Token(Token::Op(op)), _)) = self.tokens.peek() {
+                let op = *op;
+                if op.precedence() < min {
                     break;
                 }
 
-                i += 1;
+                self.next();
+                let next_min = match op.right_associative() {
+                    true => op.precedence(),
+                    false => op.precedence() + 1,
+                };
+                let right = self.expression(next_min)?;
+                left = Token::Tree(op, Box::new(left), Box::new(right));
             }
-        }
 
-        if tokens.len() != 1 || !matches!(tokens[0], Token::Tree(..)) {
-            return Err(ProcesResult::Constraint);
+            Ok(left)
         }
 
-        Ok(Constraint(tokens[0].clone()))
+        fn operand(&mut self) -> Result<Token, ParseError> {
+            match self.next() {
+                Some((Lexeme::Token(x @ (Token::Element(_) | Token::Held(..))), _)) => Ok(x),
+                Some((Lexeme::Open, open)) => {
+                    let inner = self.expression(0)?;
+                    match self.next() {
+                        Some((Lexeme::Close, _)) => Ok(inner),
+                        _ => Err(ParseError::new(ProcesResult::Unclosed, open)),
+                    }
+                }
+                Some((Lexeme::Close, span)) => {
+                    Err(ParseError::new(ProcesResult::Character(')'), span))
+                }
+                Some((_, span)) => Err(ParseError::new(ProcesResult::Dangling, span)),
+                None => Err(ParseError::new(
+                    ProcesResult::Dangling,
+                    self.previous.clone(),
+                )),
+            }
+        }
     }
 
-    fn safe_remove(tokens: &mut Vec<Token>, index: isize) -> Result<Token, ProcesResult> {
-        if index < 0 || index as usize >= tokens.len() {
-            return Err(ProcesResult::Constraint);
+    // Put the tree in a standard form so equivalent constraints compare equal.
+    // Chains of `|`, `^` and `<->` are flattened and sorted, `|` is also deduplicated:
+    // `l3 | w1 | w1` -> `w1 | l3`
+    pub fn canonicalize(token: Token) -> Token {
+        let (op, left, right) = match token {
+            Token::Tree(op, left, right) => (op, left, right),
+            x => return x,
+        };
+
+        if !op.commutative() {
+            return Token::Tree(
+                op,
+                Box::new(canonicalize(*left)),
+                Box::new(canonicalize(*right)),
+            );
         }
 
-        Ok(tokens.remove(index as usize))
+        let mut operands = Vec::new();
+        for i in [*left, *right] {
+            match canonicalize(i) {
+                Token::Tree(x, a, b) if x == op => {
+                    operands.extend(flatten_op(Token::Tree(x, a, b), op))
+                }
+                x => operands.push(x),
+            }
+        }
+
+        operands.sort();
+        // `a ^ a` is not `a`, so only `|` can drop repeats
+        if op == Ops::Or {
+            operands.dedup();
+        }
+
+        let mut operands = operands.into_iter();
+        let first = operands.next().unwrap();
+        operands.fold(first, |acc, x| Token::Tree(op, Box::new(acc), Box::new(x)))
+    }
+
+    // The operands of a chain of the same operator
+    fn flatten_op(token: Token, op: Ops) -> Vec<Token> {
+        match token {
+            Token::Tree(x, a, b) if x == op => {
+                let mut out = flatten_op(*a, op);
+                out.extend(flatten_op(*b, op));
+                out
+            }
+            x => vec![x],
+        }
     }
 }
 
@@ -243,12 +608,41 @@ impl Deref for Constraint {
     }
 }
 
+impl Token {
+    // Does this need brackets as the `left` or right operand of `parent`
+    fn needs_brackets(&self, parent: Ops, left: bool) -> bool {
+        let op = match self {
+            Token::Tree(op, ..) => op,
+            _ => return false,
+        };
+
+        match op.precedence().cmp(&parent.precedence()) {
+            Ordering::Greater => false,
+            Ordering::Less => true,
+            Ordering::Equal => left == parent.right_associative(),
+        }
+    }
+}
+
 impl Display for Token {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Token::Op(op) => write!(f, "{}", op),
             Token::Element(id) => write!(f, "{}", id),
-            Token::Tree(op, left, right) => write!(f, "{} {} {}", left, op, right),
+            Token::Held(id, player) => write!(f, "{}@{}", id, player + 1),
+            Token::Tree(op, left, right) => {
+                for (side, is_left) in [(left, true), (right, false)] {
+                    if !is_left {
+                        write!(f, " {} ", op)?;
+                    }
+
+                    match side.needs_brackets(*op, is_left) {
+                        true => write!(f, "({})", side)?,
+                        false => write!(f, "{}", side)?,
+                    }
+                }
+                Ok(())
+            }
         }
     }
 }
@@ -257,6 +651,333 @@ impl Display for Ops {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Ops::Or => write!(f, "|"),
+            Ops::Xor => write!(f, "^"),
+            Ops::Implies => write!(f, "->"),
+            Ops::Iff => write!(f, "<->"),
         }
     }
 }
+
+pub mod simplify {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum Simplified {
+        // A confirmed element already makes it true
+        Satisfied,
+        // Can no longer be true, for `|` every element has been dismissed
+        Violated,
+        // Another constraint (by index) says the same thing or more
+        Subsumed(usize),
+        // What is left after removing dismissed elements
+        Reduced(Vec<ElementIdentifier>),
+        // Couldn't be simplified
+        Unchanged,
+    }
+
+    // Rewrite each constraint against the known states, then drop clauses that
+    // contain every element of another one as they can never tell us more.
+    pub fn simplify(constraints: &[Constraint], elements: &Elements) -> Vec<Simplified> {
+        let mut out = constraints
+            .iter()
+            .map(|x| reduce(x, elements))
+            .collect::<Vec<_>>();
+
+        for i in 0..out.len() {
+            let clause = match &out[i] {
+                Simplified::Reduced(x) => x.clone(),
+                _ => continue,
+            };
+
+            let subsumed_by = out.iter().enumerate().position(|(j, x)| match x {
+                Simplified::Reduced(other) if i != j => {
+                    other.iter().all(|x| clause.contains(x))
+                        && (other.len() < clause.len() || j < i)
+                }
+                _ => false,
+            });
+
+            if let Some(j) = subsumed_by {
+                out[i] = Simplified::Subsumed(j);
+            }
+        }
+
+        out
+    }
+
+    fn reduce(constraint: &Constraint, elements: &Elements) -> Simplified {
+        let clause = match constraint.clause() {
+            Some(x) => x,
+            None => {
+                return match constraint.outcome(elements) {
+                    Some(true) => Simplified::Satisfied,
+                    Some(false) => Simplified::Violated,
+                    None => Simplified::Unchanged,
+                }
+            }
+        };
+
+        if clause
+            .iter()
+            .any(|x| elements.get_state(x) == Some(ElementState::Confirmed))
+        {
+            return Simplified::Satisfied;
+        }
+
+        let left = clause
+            .into_iter()
+            .filter(|x| elements.get_state(x) == Some(ElementState::Unknown))
+            .collect::<Vec<_>>();
+
+        match left.is_empty() {
+            true => Simplified::Violated,
+            false => Simplified::Reduced(left),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deck() -> Elements {
+        let mut out = Elements::numbered(6);
+        out.players = vec!["A".to_owned(), "B".to_owned(), "C".to_owned()];
+        out
+    }
+
+    fn parse(raw: &str) -> Constraint {
+        Constraint::parse(raw, &deck()).unwrap()
+    }
+
+    fn error(raw: &str) -> (ProcesResult, Range<usize>) {
+        let e = Constraint::parse(raw, &deck()).unwrap_err();
+        (e.kind, e.span)
+    }
+
+    #[test]
+    fn precedence() {
+        // `|` binds tightest, then `^`, `->` and `<->`
+        assert_eq!(parse("w1 | w2 ^ w3"), parse("(w1 | w2) ^ w3"));
+        assert_eq!(parse("w1 ^ w2 | w3"), parse("w1 ^ (w2 | w3)"));
+        assert_eq!(parse("w1 ^ w2 -> w3"), parse("(w1 ^ w2) -> w3"));
+        assert_eq!(parse("w1 -> w2 <-> w3"), parse("(w1 -> w2) <-> w3"));
+        assert_eq!(parse("w1 <-> w2 -> w3"), parse("w1 <-> (w2 -> w3)"));
+        assert_ne!(parse("w1 | w2 ^ w3"), parse("w1 | (w2 ^ w3)"));
+    }
+
+    #[test]
+    fn implies_groups_from_the_right() {
+        assert_eq!(parse("w1 -> w2 -> w3"), parse("w1 -> (w2 -> w3)"));
+        assert_ne!(parse("w1 -> w2 -> w3"), parse("(w1 -> w2) -> w3"));
+    }
+
+    #[test]
+    fn error_spans() {
+        assert_eq!(error(""), (ProcesResult::Constraint, 0..0));
+        assert_eq!(error("w1"), (ProcesResult::Trivial, 0..2));
+        assert_eq!(error("w1 |"), (ProcesResult::Dangling, 3..4));
+        assert_eq!(error("-> w1"), (ProcesResult::Dangling, 0..2));
+        assert_eq!(error("w1 | x2"), (ProcesResult::Section, 5..7));
+        assert_eq!(error("w1 | wa"), (ProcesResult::Index, 6..7));
+        assert_eq!(error("w1 | w12"), (ProcesResult::IndexRange(6), 6..8));
+        assert_eq!(error("w1 w2"), (ProcesResult::Operator, 3..5));
+        assert_eq!(error("(w1 | w2"), (ProcesResult::Unclosed, 0..1));
+        assert_eq!(error("w1 | w2)"), (ProcesResult::Character(')'), 7..8));
+        assert_eq!(error("w1 & w2"), (ProcesResult::Character('&'), 3..4));
+        // Counted in characters, not bytes
+        assert_eq!(error("w1 → w2"), (ProcesResult::Character('→'), 3..4));
+        assert_eq!(error("w1 | ü2"), (ProcesResult::Section, 5..7));
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for (raw, shown) in [
+            ("w1 | w2", "w1 | w2"),
+            ("(w1 | w2) ^ w3", "w3 ^ w1 | w2"),
+            ("w1 | (w2 ^ w3)", "w1 | (w2 ^ w3)"),
+            ("w1 -> (w2 -> w3)", "w1 -> w2 -> w3"),
+            ("(w1 -> w2) -> w3", "(w1 -> w2) -> w3"),
+            ("(w1 <-> w2) -> w3", "(w1 <-> w2) -> w3"),
+            ("((w1))  <->w2", "w1 <-> w2"),
+        ] {
+            let constraint = parse(raw);
+            assert_eq!(constraint.to_string(), shown);
+            assert_eq!(parse(shown), constraint);
+        }
+    }
+
+    #[test]
+    fn or_drops_repeats() {
+        assert_eq!(parse("l3 | w1 | w1"), parse("w1 | l3"));
+        assert_eq!(parse("w1 | (l3 | w1)").to_string(), "w1 | l3");
+    }
+
+    #[test]
+    fn xor_keeps_repeats() {
+        assert_eq!(parse("w1 ^ l3 ^ w1").to_string(), "w1 ^ w1 ^ l3");
+    }
+
+    #[test]
+    fn implies_is_not_sorted() {
+        assert_eq!(parse("l1 -> w1").to_string(), "l1 -> w1");
+        assert_ne!(parse("l1 -> w1"), parse("w1 -> l1"));
+        // Its operands are still put in order
+        assert_eq!(
+            parse("(l2 | w1) -> (p1 | l1)").to_string(),
+            "w1 | l2 -> l1 | p1"
+        );
+    }
+
+    #[test]
+    fn canonical_form_is_stable() {
+        for raw in [
+            "p2 | l1 | w3 | l1",
+            "(p2 -> w1) <-> l3 ^ l4",
+            "w1 -> w2 -> w3",
+            "(w1 -> w2) -> w3",
+            "l1 ^ (w2 | p3) <-> (w1 <-> w2)",
+        ] {
+            let once = parse(raw);
+            let twice = parse(&once.to_string());
+            assert_eq!(once, twice, "{}", raw);
+            assert_eq!(once.to_string(), twice.to_string());
+        }
+    }
+
+    #[test]
+    fn holdings() {
+        assert_eq!(parse("w1@2 | l1").to_string(), "l1 | w1@2");
+        assert_eq!(parse("w1@2 | w1@1 | w1@2").to_string(), "w1@1 | w1@2");
+        assert_eq!(error("l1 | w1@4"), (ProcesResult::Player, 7..9));
+        assert_eq!(error("l1 | w1@"), (ProcesResult::Player, 7..8));
+        assert_eq!(error("w1@1"), (ProcesResult::Trivial, 0..4));
+    }
+
+    #[test]
+    fn shown_card_is_found() {
+        let mut elements = deck();
+        let constraint = parse("l1@3 | p1@3 | w1@3");
+        assert!(matches!(constraint.solveable(&elements), Solvable::No));
+        assert_eq!(constraint.outcome(&elements), None);
+
+        // C can't hold two of them so has to hold the last, which keeps it out of the envelope
+        assert!(elements.process_action("p1!3 w1!3").is_none());
+        let solution = constraint.solve(&elements).unwrap();
+        assert_eq!(
+            (
+                solution.element.to_string(),
+                solution.state,
+                solution.holder
+            ),
+            ("l1".to_owned(), SolvedState::Dismissed, Some((2, true)))
+        );
+
+        // Until they are known to not hold it either
+        assert!(elements.process_action("l1!3").is_none());
+        assert_eq!(constraint.outcome(&elements), Some(false));
+    }
+
+    #[test]
+    fn unshown_card_is_in_the_envelope() {
+        let mut elements = deck();
+        let constraint = parse("l1 | l1@1");
+        assert!(matches!(constraint.solveable(&elements), Solvable::No));
+
+        assert!(elements.process_action("l1!1").is_none());
+        let solution = constraint.solve(&elements).unwrap();
+        assert_eq!(
+            (
+                solution.element.to_string(),
+                solution.state,
+                solution.holder
+            ),
+            ("l1".to_owned(), SolvedState::Confirmed, None)
+        );
+
+        // Out of the envelope means Alice has it
+        let mut elements = deck();
+        assert!(elements.process_action("l1d").is_none());
+        let solution = constraint.solve(&elements).unwrap();
+        assert_eq!(
+            (
+                solution.element.to_string(),
+                solution.state,
+                solution.holder
+            ),
+            ("l1".to_owned(), SolvedState::Dismissed, Some((0, true)))
+        );
+    }
+
+    // Simplify `constraints` after the element command `actions`
+    fn simplified(constraints: &[&str], actions: &str) -> Vec<simplify::Simplified> {
+        let mut elements = deck();
+        if !actions.is_empty() {
+            assert!(elements.process_action(actions).is_none());
+        }
+        let constraints = constraints.iter().map(|x| parse(x)).collect::<Vec<_>>();
+        simplify::simplify(&constraints, &elements)
+    }
+
+    fn reduced(raw: &[&str]) -> simplify::Simplified {
+        let elements = deck();
+        simplify::Simplified::Reduced(raw.iter().map(|x| elements.parse_id(x).unwrap()).collect())
+    }
+
+    #[test]
+    fn known_states_settle_constraints() {
+        use simplify::Simplified::*;
+        assert_eq!(simplified(&["w1 | w2"], "w1c"), [Satisfied]);
+        assert_eq!(simplified(&["w1 | w2"], "w1,2d"), [Violated]);
+        assert_eq!(
+            simplified(&["w1 | w2 | w3"], "w2d"),
+            [reduced(&["w1", "w3"])]
+        );
+
+        // Anything other than `|` is only settled once its outcome is known
+        assert_eq!(simplified(&["w1 ^ w2"], "w1c w2d"), [Satisfied]);
+        assert_eq!(simplified(&["w1 ^ w2"], "w1,2c"), [Violated]);
+        assert_eq!(simplified(&["w1 ^ w2"], "w1c"), [Unchanged]);
+    }
+
+    #[test]
+    fn smaller_clauses_subsume_larger_ones() {
+        use simplify::Simplified::*;
+        // Knowing w1 or l3 makes also adding p5 say nothing new, whichever comes first
+        assert_eq!(
+            simplified(&["w1 | l3", "w1 | l3 | p5"], ""),
+            [reduced(&["w1", "l3"]), Subsumed(0)]
+        );
+        assert_eq!(
+            simplified(&["w1 | l3 | p5", "w1 | l3"], ""),
+            [Subsumed(1), reduced(&["w1", "l3"])]
+        );
+
+        // Clauses that become equal keep the first
+        assert_eq!(
+            simplified(&["w1 | w2 | w3", "w1 | w2 | w4"], "w3,4d"),
+            [reduced(&["w1", "w2"]), Subsumed(0)]
+        );
+
+        // Each clause points at the first smaller one still standing, so only the smallest is left
+        assert_eq!(
+            simplified(&["w1 | w2 | w3", "w1 | w2", "w1 | w2 | w3 | w4"], ""),
+            [Subsumed(1), reduced(&["w1", "w2"]), Subsumed(1)]
+        );
+        assert_eq!(
+            simplified(&["w1 | w2 | w3 | w4", "w1 | w2 | w3", "w1 | w2"], ""),
+            [Subsumed(1), Subsumed(2), reduced(&["w1", "w2"])]
+        );
+
+        // Satisfied clauses are settled before anything can subsume them
+        assert_eq!(
+            simplified(&["w1 | w2", "w1 | w2 | w3"], "w2c"),
+            [Satisfied, Satisfied]
+        );
+        // Overlapping isn't enough
+        assert_eq!(
+            simplified(&["w1 | l1", "w1 | l2"], ""),
+            [reduced(&["w1", "l1"]), reduced(&["w1", "l2"])]
+        );
+    }
+}
@@ -1,9 +1,11 @@
 use std::{
     fmt::{self, Display, Formatter},
     fs,
+    ops::Range,
     path::Path,
 };
 
+use hashbrown::HashSet;
 use toml::Value;
 
 #[derive(Debug, Clone)]
@@ -11,6 +13,7 @@ pub struct Elements {
     pub locations: Vec<Element>,
     pub people: Vec<Element>,
     pub weapons: Vec<Element>,
+    pub players: Vec<String>,
     pub max_name_length: usize,
 }
 
@@ -18,15 +21,23 @@ pub struct Elements {
 pub struct Element {
     pub name: String,
     pub state: ElementState,
+    // The state was applied from a solver deduction rather than entered
+    pub derived: bool,
+    // Index of the player known to hold this card
+    pub owner: Option<usize>,
+    // Players known not to hold this card
+    pub excluded: HashSet<usize>,
+    // Players whose entry in `owner` or `excluded` was applied from a solver deduction
+    pub derived_holders: HashSet<usize>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct ElementIdentifier {
     pub element_type: ElementType,
     pub index: usize,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 
 pub enum ElementType {
     Weapon,
@@ -41,98 +52,434 @@ pub enum ElementState {
     Dismissed,
 }
 
+// What is known about a single cell of the notepad
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Knowledge {
+    Unknown,
+    // Entered by the user
+    Known(bool),
+    // Follows from what the user entered
+    Deduced(bool),
+}
+
 impl Elements {
     pub fn load(path: impl AsRef<Path>) -> Option<Self> {
-        let mut max_name_length = 0;
-        let mut load_section = |section: &str, toml: &Value| -> Option<Vec<Element>> {
-            let elements = toml
-                .get(section)?
-                .as_array()?
-                .iter()
-                .filter_map(|v| v.as_str())
-                .map(|x| Element::new(x.to_owned()))
-                .collect::<Vec<_>>();
-            max_name_length =
-                max_name_length.max(elements.iter().map(|x| x.name.len()).max().unwrap());
-            Some(elements)
-        };
-
         let raw = fs::read_to_string(path).ok()?;
         let toml = toml::from_str::<Value>(&raw).ok()?;
+        let names = |section: &str| -> Option<Vec<String>> {
+            Some(
+                toml.get(section)?
+                    .as_array()?
+                    .iter()
+                    .filter_map(|v| v.as_str())
+                    .map(|x| x.to_owned())
+                    .collect(),
+            )
+        };
+
+        let mut out = Self::new(names("locations")?, names("people")?, names("weapons")?);
+        out.players = names("players").unwrap_or_default();
+        Some(out)
+    }
 
-        Some(Self {
-            locations: load_section("locations", &toml)?,
-            people: load_section("people", &toml)?,
-            weapons: load_section("weapons", &toml)?,
+    // A deck with these cards, nothing known about them and no players
+    pub fn new(locations: Vec<String>, people: Vec<String>, weapons: Vec<String>) -> Self {
+        let max_name_length = locations
+            .iter()
+            .chain(people.iter())
+            .chain(weapons.iter())
+            .map(|x| x.len())
+            .max()
+            .unwrap_or(0);
+        let section = |names: Vec<String>| names.into_iter().map(Element::new).collect();
+
+        Self {
+            locations: section(locations),
+            people: section(people),
+            weapons: section(weapons),
+            players: Vec::new(),
             max_name_length,
-        })
+        }
     }
 
+    // `n` of each card named Location1, Person1 and so on, for tests
+    #[doc(hidden)]
+    pub fn numbered(n: usize) -> Self {
+        let names = |prefix: &str| (1..=n).map(|x| format!("{}{}", prefix, x)).collect();
+        Self::new(names("Location"), names("Person"), names("Weapon"))
+    }
+
+    // Set element states, several at once with lists and ranges:
+    // w2d, w2,5d, l1-3c, p1@2, w2d l4d, mine w2 l4 p1
+    // Every part is checked before anything changes so a batch is all or nothing
     pub fn process_action(&mut self, inp: &str) -> Option<Option<String>> {
+        // Cards in our hand can't be in the envelope
+        let (parts, mine) = match inp.strip_prefix("mine ") {
+            Some(x) => (x, true),
+            None => (inp, false),
+        };
+
+        let mut out = self.clone();
+        for (i, part) in parts.split_whitespace().enumerate() {
+            let part = match mine {
+                true => format!("{}d", part),
+                false => part.to_owned(),
+            };
+
+            match out.process_single(&part) {
+                None => {}
+                // Without an action its probably a constraint, let that report the error
+                Some(None) if i == 0 && !mine => return Some(None),
+                Some(None) => return Some(Some(format!("Invalid element '{}'", part))),
+                Some(x) => return Some(x),
+            }
+        }
+
+        *self = out;
+        None
+    }
+
+    fn process_single(&mut self, inp: &str) -> Option<Option<String>> {
         let mut chars = inp.chars();
-        let section = match chars.next() {
-            Some('l') => &mut self.locations,
-            Some('p') => &mut self.people,
-            Some('w') => &mut self.weapons,
-            _ => return Some(None),
+        let element_type = match chars.next().and_then(ElementType::from_char) {
+            Some(x) => x,
+            None => return Some(None),
         };
 
-        let index = chars
+        let list = chars
             .clone()
-            .take_while(|x| x.is_ascii_digit())
+            .take_while(|x| x.is_ascii_digit() || matches!(x, ',' | '-'))
             .collect::<String>();
-        let index_len = index.len();
-        let index = match index.parse::<usize>().ok().map(|x| x.saturating_sub(1)) {
-            Some(x) if x >= section.len() => return Some(Some("Invalid index".to_owned())),
-            Some(x) => x,
-            None => return Some(None),
+        let action = chars.nth(list.chars().count());
+        if list.is_empty() || !matches!(action, Some('c' | 'd' | 'u' | '@' | '!')) {
+            return Some(None);
+        }
+
+        let len = self.section(element_type).len();
+        let indexes = match parse_indexes(&list, len) {
+            Ok(x) => x,
+            Err(e) => return Some(Some(e.to_string())),
         };
 
-        let new_state = match chars.nth(index_len) {
+        let rest = chars.as_str();
+        let new_state = match action {
             Some('c') => ElementState::Confirmed,
             Some('d') => ElementState::Dismissed,
             Some('u') => ElementState::Unknown,
+            Some(x @ ('@' | '!')) => {
+                let player = match rest {
+                    "-" => None,
+                    x => match x.parse::<usize>().ok().map(|x| x.wrapping_sub(1)) {
+                        Some(x) if x < self.players.len() => Some(x),
+                        _ => return Some(Some("Invalid player".to_owned())),
+                    },
+                };
+
+                for index in indexes {
+                    let element = &mut self.section_mut(element_type)[index];
+                    match player {
+                        Some(player) => element.set_owner(player, x == '@'),
+                        None => {
+                            element.owner = None;
+                            element.excluded.clear();
+                            element.derived_holders.clear();
+                        }
+                    }
+                }
+                return None;
+            }
             _ => return Some(None),
         };
 
-        section[index].state = new_state;
+        if let Some(x) = rest.chars().next() {
+            return Some(Some(ProcesResult::Character(x).to_string()));
+        }
+
+        for index in indexes {
+            let element = &mut self.section_mut(element_type)[index];
+            element.state = new_state;
+            element.derived = false;
+        }
         None
     }
 
-    pub fn get_state(&self, id: &ElementIdentifier) -> ElementState {
-        let list = match id.element_type {
-            ElementType::Location => &self.locations,
-            ElementType::Person => &self.people,
-            ElementType::Weapon => &self.weapons,
+    // What is known about `player` holding the card `id`
+    pub fn player_knowledge(&self, id: &ElementIdentifier, player: usize) -> Knowledge {
+        let element = match self.get(id) {
+            Some(x) => x,
+            None => return Knowledge::Unknown,
         };
 
-        if id.index >= list.len() {
-            return ElementState::Unknown;
+        let known = |x| match element.derived_holders.contains(&player) {
+            true => Knowledge::Deduced(x),
+            false => Knowledge::Known(x),
+        };
+        if element.owner == Some(player) {
+            return known(true);
+        }
+
+        if element.excluded.contains(&player) {
+            return known(false);
         }
 
-        list[id.index].state
+        // Someone else has it, or its in the envelope
+        if element.owner.is_some() || element.state == ElementState::Confirmed {
+            return Knowledge::Deduced(false);
+        }
+
+        // Not in the envelope and everyone else has been ruled out
+        let possible = (0..self.players.len())
+            .filter(|x| !element.excluded.contains(x))
+            .collect::<Vec<_>>();
+        if element.state == ElementState::Dismissed && possible == [player] {
+            return Knowledge::Deduced(true);
+        }
+
+        Knowledge::Unknown
     }
 
-    pub fn set_state(&mut self, id: &ElementIdentifier, state: ElementState) {
-        let list = match id.element_type {
+    // What is known about the card `id` being in the envelope
+    pub fn envelope_knowledge(&self, id: &ElementIdentifier) -> Knowledge {
+        let element = match self.get(id) {
+            Some(x) => x,
+            None => return Knowledge::Unknown,
+        };
+
+        match (element.state, element.derived) {
+            (ElementState::Confirmed, false) => return Knowledge::Known(true),
+            (ElementState::Dismissed, false) => return Knowledge::Known(false),
+            (ElementState::Confirmed, true) => return Knowledge::Deduced(true),
+            (ElementState::Dismissed, true) => return Knowledge::Deduced(false),
+            (ElementState::Unknown, _) => {}
+        }
+
+        if element.owner.is_some() {
+            return Knowledge::Deduced(false);
+        }
+
+        if !self.players.is_empty()
+            && (0..self.players.len()).all(|x| element.excluded.contains(&x))
+        {
+            return Knowledge::Deduced(true);
+        }
+
+        Knowledge::Unknown
+    }
+
+    // Change who is playing, keeping what is known about players who are still in by name
+    pub fn set_players(&mut self, players: Vec<String>) {
+        let moved = self
+            .players
+            .iter()
+            .map(|x| players.iter().position(|y| y == x))
+            .collect::<Vec<_>>();
+        let remap = |x: usize| moved.get(x).copied().flatten();
+
+        for element in self
+            .locations
+            .iter_mut()
+            .chain(self.people.iter_mut())
+            .chain(self.weapons.iter_mut())
+        {
+            element.owner = element.owner.and_then(remap);
+            element.excluded = element.excluded.iter().filter_map(|x| remap(*x)).collect();
+            element.derived_holders = element
+                .derived_holders
+                .iter()
+                .filter_map(|x| remap(*x))
+                .collect();
+        }
+        self.players = players;
+    }
+
+    pub fn section(&self, element_type: ElementType) -> &[Element] {
+        match element_type {
+            ElementType::Location => &self.locations,
+            ElementType::Person => &self.people,
+            ElementType::Weapon => &self.weapons,
+        }
+    }
+
+    fn section_mut(&mut self, element_type: ElementType) -> &mut Vec<Element> {
+        match element_type {
             ElementType::Location => &mut self.locations,
             ElementType::Person => &mut self.people,
             ElementType::Weapon => &mut self.weapons,
+        }
+    }
+
+    // Every element in the deck, locations then people then weapons
+    pub fn ids(&self) -> Vec<ElementIdentifier> {
+        [
+            ElementType::Location,
+            ElementType::Person,
+            ElementType::Weapon,
+        ]
+        .into_iter()
+        .flat_map(|element_type| {
+            (0..self.section(element_type).len()).map(move |index| ElementIdentifier {
+                element_type,
+                index,
+            })
+        })
+        .collect()
+    }
+
+    // Look up an element written like `w2`
+    pub fn parse_id(&self, raw: &str) -> Option<ElementIdentifier> {
+        let mut chars = raw.chars();
+        let element_type = ElementType::from_char(chars.next()?)?;
+        let index = chars.as_str().parse::<usize>().ok()?.checked_sub(1)?;
+        let id = ElementIdentifier {
+            element_type,
+            index,
         };
 
+        self.contains(&id).then_some(id)
+    }
+
+    pub fn get(&self, id: &ElementIdentifier) -> Option<&Element> {
+        self.section(id.element_type).get(id.index)
+    }
+
+    pub fn contains(&self, id: &ElementIdentifier) -> bool {
+        id.index < self.section(id.element_type).len()
+    }
+
+    // None if the id isn't in this deck, like one from a save made with a different deck
+    pub fn get_state(&self, id: &ElementIdentifier) -> Option<ElementState> {
+        self.get(id).map(|x| x.state)
+    }
+
+    pub fn set_state(&mut self, id: &ElementIdentifier, state: ElementState) {
+        let list = self.section_mut(id.element_type);
+
         if id.index >= list.len() {
             return;
         }
 
         list[id.index].state = state;
     }
+
+    // Record that `player` does or doesn't hold the card `id`
+    pub fn set_owner(&mut self, id: &ElementIdentifier, player: usize, owns: bool) {
+        if let Some(x) = self.section_mut(id.element_type).get_mut(id.index) {
+            x.set_owner(player, owns);
+        }
+    }
+
+    // Set a state worked out by the solver, user entered states are left alone
+    pub fn derive_state(&mut self, id: &ElementIdentifier, state: ElementState) -> bool {
+        let list = self.section_mut(id.element_type);
+
+        match list.get_mut(id.index) {
+            Some(x) if x.state == ElementState::Unknown => {
+                x.state = state;
+                x.derived = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Record who holds a card as worked out by the solver, what the user entered is left alone
+    pub fn derive_owner(&mut self, id: &ElementIdentifier, player: usize, owns: bool) -> bool {
+        if self.player_knowledge(id, player) != Knowledge::Unknown {
+            return false;
+        }
+
+        let element = match self.section_mut(id.element_type).get_mut(id.index) {
+            Some(x) => x,
+            None => return false,
+        };
+        // Holding it keeps the card out of the envelope, which is derived too unless it was entered
+        let derived = element.derived || element.state == ElementState::Unknown;
+        element.set_owner(player, owns);
+        element.derived_holders.insert(player);
+        if owns {
+            element.derived = derived;
+        }
+        true
+    }
+
+    // Copy with only the states the user entered
+    pub fn without_derived(&self) -> Self {
+        let mut out = self.clone();
+        out.clear_derived();
+        out
+    }
+
+    // Reset every state and holder that came from the solver back to unknown
+    pub fn clear_derived(&mut self) {
+        for element in [&mut self.locations, &mut self.people, &mut self.weapons]
+            .into_iter()
+            .flatten()
+        {
+            if element.derived {
+                element.state = ElementState::Unknown;
+                element.derived = false;
+            }
+
+            for player in element.derived_holders.drain() {
+                if element.owner == Some(player) {
+                    element.owner = None;
+                }
+                element.excluded.remove(&player);
+            }
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 pub enum ProcesResult {
     Section,
     Index,
+    // Index past the end of a section with this many elements
+    IndexRange(usize),
+    Character(char),
+    // An operator without something on one side
+    Dangling,
+    // Two elements without an operator between them
+    Operator,
+    // A bracket that is never closed
+    Unclosed,
     Constraint,
+    Trivial,
+    // `@` without a player number in range
+    Player,
+}
+
+// An error in a constraint with the characters it applies to
+#[derive(Debug)]
+pub struct ParseError {
+    pub kind: ProcesResult,
+    pub span: Range<usize>,
+}
+
+// Turn `2,5` or `1-3` into zero based indexes into a section of `len` elements
+fn parse_indexes(list: &str, len: usize) -> Result<Vec<usize>, ProcesResult> {
+    let index = |x: &str| match x.parse::<usize>() {
+        // Indexes start at 1
+        Ok(x) if (1..=len).contains(&x) => Ok(x - 1),
+        Ok(_) => Err(ProcesResult::IndexRange(len)),
+        Err(_) => Err(ProcesResult::Index),
+    };
+
+    let mut out = Vec::new();
+    for item in list.split(',') {
+        match item.split_once('-') {
+            Some((start, end)) => {
+                let (start, end) = (index(start)?, index(end)?);
+                if start > end {
+                    return Err(ProcesResult::Index);
+                }
+                out.extend(start..=end);
+            }
+            None => out.push(index(item)?),
+        }
+    }
+
+    Ok(out)
 }
 
 impl Element {
@@ -140,16 +487,113 @@ impl Element {
         Self {
             name,
             state: ElementState::Unknown,
+            derived: false,
+            owner: None,
+            excluded: HashSet::new(),
+            derived_holders: HashSet::new(),
+        }
+    }
+
+    // Record that `player` does or doesn't hold this card.
+    // A card held by a player can't be in the envelope.
+    fn set_owner(&mut self, player: usize, owns: bool) {
+        self.derived_holders.remove(&player);
+        if !owns {
+            self.excluded.insert(player);
+            if self.owner == Some(player) {
+                self.owner = None;
+            }
+            return;
+        }
+
+        self.owner = Some(player);
+        self.excluded.remove(&player);
+        self.state = ElementState::Dismissed;
+        self.derived = false;
+    }
+}
+
+impl ElementType {
+    pub fn from_char(chr: char) -> Option<Self> {
+        Some(match chr {
+            'l' => Self::Location,
+            'p' => Self::Person,
+            'w' => Self::Weapon,
+            _ => return None,
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Location => "location",
+            Self::Person => "person",
+            Self::Weapon => "weapon",
+        }
+    }
+}
+
+impl ElementState {
+    // Unknown -> Confirmed -> Dismissed -> Unknown
+    pub fn cycle(self) -> Self {
+        match self {
+            Self::Unknown => Self::Confirmed,
+            Self::Confirmed => Self::Dismissed,
+            Self::Dismissed => Self::Unknown,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Unknown => "unknown",
+            Self::Confirmed => "confirmed",
+            Self::Dismissed => "dismissed",
         }
     }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "unknown" => Self::Unknown,
+            "confirmed" => Self::Confirmed,
+            "dismissed" => Self::Dismissed,
+            _ => return None,
+        })
+    }
+
+    // The character used for this state in commands
+    pub fn as_char(self) -> char {
+        match self {
+            Self::Unknown => 'u',
+            Self::Confirmed => 'c',
+            Self::Dismissed => 'd',
+        }
+    }
+}
+
+impl ParseError {
+    pub fn new(kind: ProcesResult, span: Range<usize>) -> Self {
+        Self { kind, span }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at column {}", self.kind, self.span.start + 1)
+    }
 }
 
 impl Display for ProcesResult {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
-            Self::Section => write!(f, "Invalid section"),
+            Self::Section => write!(f, "Unknown element"),
             Self::Index => write!(f, "Invalid index"),
+            Self::IndexRange(x) => write!(f, "Index out of range (1-{})", x),
+            Self::Character(x) => write!(f, "Unexpected character '{}'", x),
+            Self::Dangling => write!(f, "Operator is missing an operand"),
+            Self::Operator => write!(f, "Expected an operator"),
+            Self::Unclosed => write!(f, "Unclosed bracket"),
             Self::Constraint => write!(f, "Invalid constraint"),
+            Self::Trivial => write!(f, "Constraint has one element, set its state instead"),
+            Self::Player => write!(f, "Invalid player"),
         }
     }
 }
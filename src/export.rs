@@ -0,0 +1,295 @@
+use std::fmt::Write;
+
+use serde_json::{json, Value};
+
+use crate::{
+    element::{ElementIdentifier, ElementState},
+    game::Game,
+    protocol,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Markdown,
+    Csv,
+    Json,
+}
+
+// One element as it appears in every format
+struct Row {
+    id: ElementIdentifier,
+    name: String,
+    // What the user entered
+    state: ElementState,
+    // What the solver worked out, "conflict" if constraints disagree
+    deduced: Option<&'static str>,
+    probability: Option<f64>,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "md" | "markdown" => Self::Markdown,
+            "csv" => Self::Csv,
+            "json" => Self::Json,
+            _ => return None,
+        })
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Markdown => "md",
+            Self::Csv => "csv",
+            Self::Json => "json",
+        }
+    }
+}
+
+// Everything known about the game: elements, constraints and the commands that got there
+pub fn export(game: &Game, format: Format) -> String {
+    let rows = rows(game);
+    match format {
+        Format::Markdown => markdown(game, &rows),
+        Format::Csv => csv(game, &rows),
+        Format::Json => json(game, &rows).to_string(),
+    }
+}
+
+fn rows(game: &Game) -> Vec<Row> {
+    let elements = game.board().elements();
+    let deductions = game.deductions();
+    let probabilities = game.board().probabilities();
+
+    elements
+        .ids()
+        .into_iter()
+        .filter_map(|id| {
+            let element = elements.get(&id)?;
+            let deduced = match deductions.states.get(&id) {
+                Some(Some(x)) => Some(x.name()),
+                Some(None) => Some("conflict"),
+                // Applied deductions without a constraint left to show them
+                None if element.derived => Some(element.state.name()),
+                None => None,
+            };
+
+            Some(Row {
+                id,
+                name: element.name.clone(),
+                state: match element.derived {
+                    true => ElementState::Unknown,
+                    false => element.state,
+                },
+                deduced,
+                probability: probabilities.get(&id).copied(),
+            })
+        })
+        .collect()
+}
+
+fn percent(probability: Option<f64>) -> String {
+    match probability {
+        Some(x) => format!("{:.1}%", x * 100.0),
+        None => "-".to_owned(),
+    }
+}
+
+fn markdown(game: &Game, rows: &[Row]) -> String {
+    let mut out = String::from("# Clue Solver\n");
+
+    let mut section = None;
+    for row in rows {
+        if section != Some(row.id.element_type) {
+            section = Some(row.id.element_type);
+            let _ = write!(
+                out,
+                "\n## {}\n\n| Id | Name | State | Deduced | Probability |\n|---|---|---|---|---|\n",
+                row.id.element_type.name()
+            );
+        }
+
+        // A `|` in a name would end the cell
+        let _ = writeln!(
+            out,
+            "| {} | {} | {} | {} | {} |",
+            row.id,
+            row.name.replace('|', "\\|"),
+            row.state.name(),
+            row.deduced.unwrap_or("-"),
+            percent(row.probability)
+        );
+    }
+
+    out.push_str("\n## Constraints\n\n");
+    for (i, constraint) in game.board().constraints().iter().enumerate() {
+        let _ = writeln!(out, "{}. `{}`", i + 1, **constraint);
+    }
+
+    out.push_str("\n## History\n\n");
+    for (command, error) in game.history() {
+        let _ = match error {
+            Some(x) => writeln!(out, "- `{}` failed: {}", command, x),
+            None => writeln!(out, "- `{}`", command),
+        };
+    }
+
+    out
+}
+
+// Quote a field if it would break the row
+fn csv_field(raw: &str) -> String {
+    match raw.contains([',', '"', '\n']) {
+        true => format!("\"{}\"", raw.replace('"', "\"\"")),
+        false => raw.to_owned(),
+    }
+}
+
+// Three tables one after the other, separated by a blank line
+fn csv(game: &Game, rows: &[Row]) -> String {
+    let mut out = String::from("id,type,name,state,deduced,probability\n");
+    for row in rows {
+        let _ = writeln!(
+            out,
+            "{},{},{},{},{},{}",
+            row.id,
+            row.id.element_type.name(),
+            csv_field(&row.name),
+            row.state.name(),
+            row.deduced.unwrap_or(""),
+            row.probability.map(|x| x.to_string()).unwrap_or_default()
+        );
+    }
+
+    out.push_str("\nconstraint,text\n");
+    for (i, constraint) in game.board().constraints().iter().enumerate() {
+        let _ = writeln!(out, "{},{}", i + 1, csv_field(&constraint.to_string()));
+    }
+
+    out.push_str("\ncommand,error\n");
+    for (command, error) in game.history() {
+        let _ = writeln!(
+            out,
+            "{},{}",
+            csv_field(command),
+            csv_field(error.as_deref().unwrap_or(""))
+        );
+    }
+
+    out
+}
+
+fn json(game: &Game, rows: &[Row]) -> Value {
+    let mut out = protocol::state(game);
+    out["elements"] = rows
+        .iter()
+        .map(|row| {
+            json!({
+                "id": row.id.to_string(),
+                "type": row.id.element_type.name(),
+                "name": row.name,
+                "state": row.state.name(),
+                "deduced": row.deduced,
+                "probability": row.probability,
+            })
+        })
+        .collect();
+    out["history"] = game
+        .history()
+        .iter()
+        .map(|(command, error)| json!({ "command": command, "error": error }))
+        .collect();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{board::Board, element::Elements};
+
+    fn game() -> Game {
+        let mut elements = Elements::numbered(3);
+        elements.locations[0].name = "Hall | East".to_owned();
+        elements.people[0].name = "Col. \"Mustard\", Jr".to_owned();
+
+        let mut game = Game::new(Board::new(elements));
+        for command in ["l1c", "w1 | w2", "w2d", "nonsense"] {
+            let _ = game.execute(command);
+        }
+        game
+    }
+
+    #[test]
+    fn csv_fields_are_quoted() {
+        assert_eq!(csv_field("Lounge"), "Lounge");
+        assert_eq!(csv_field("Hall, East"), "\"Hall, East\"");
+        assert_eq!(csv_field("The \"Study\""), "\"The \"\"Study\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn markdown_tables() {
+        let out = export(&game(), Format::Markdown);
+        let lines = out.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "# Clue Solver");
+        assert_eq!(
+            lines[4..8],
+            [
+                "| Id | Name | State | Deduced | Probability |",
+                "|---|---|---|---|---|",
+                "| l1 | Hall \\| East | confirmed | - | 100.0% |",
+                "| l2 | Location2 | unknown | - | 0.0% |",
+            ]
+        );
+        assert!(lines.contains(&"| w1 | Weapon1 | unknown | confirmed | 100.0% |"));
+        assert!(out.ends_with(
+            "## Constraints\n\n1. `w1 | w2`\n\n## History\n\n\
+             - `l1c`\n- `w1 | w2`\n- `w2d`\n\
+             - `nonsense` failed: Unknown element at column 1\n"
+        ));
+    }
+
+    #[test]
+    fn csv_tables() {
+        let out = export(&game(), Format::Csv);
+        let lines = out.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines[..5],
+            [
+                "id,type,name,state,deduced,probability",
+                "l1,location,Hall | East,confirmed,,1",
+                "l2,location,Location2,unknown,,0",
+                "l3,location,Location3,unknown,,0",
+                "p1,person,\"Col. \"\"Mustard\"\", Jr\",unknown,,0.3333333333333333",
+            ]
+        );
+        assert!(lines.contains(&"w1,weapon,Weapon1,unknown,confirmed,1"));
+        assert!(out.ends_with(
+            "\nconstraint,text\n1,w1 | w2\n\ncommand,error\n\
+             l1c,\nw1 | w2,\nw2d,\nnonsense,Unknown element at column 1\n"
+        ));
+    }
+
+    #[test]
+    fn json_has_the_state_and_history() {
+        let out = serde_json::from_str::<Value>(&export(&game(), Format::Json)).unwrap();
+        let elements = out["elements"].as_array().unwrap();
+        assert_eq!(elements.len(), 9);
+        assert_eq!(
+            elements[3],
+            json!({
+                "id": "p1",
+                "type": "person",
+                "name": "Col. \"Mustard\", Jr",
+                "state": "unknown",
+                "deduced": null,
+                "probability": 1.0 / 3.0,
+            })
+        );
+        assert_eq!(elements[6]["deduced"], "confirmed");
+        assert_eq!(out["constraints"].as_array().unwrap().len(), 1);
+        assert_eq!(
+            out["history"][3],
+            json!({ "command": "nonsense", "error": "Unknown element at column 1" })
+        );
+    }
+}
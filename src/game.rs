@@ -0,0 +1,674 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    ops::Range,
+};
+
+use crate::{
+    board::{Board, BoardError, Deductions},
+    constraints::{simplify::Simplified, SolvedState},
+    element::{ElementIdentifier, ElementState},
+    export::{self, Format},
+    transcript::{self, LineError},
+};
+
+// A game being solved, driven by text commands.
+// Does no I/O, front ends show the results however they like.
+#[derive(Debug, Clone)]
+pub struct Game {
+    board: Board,
+    // The board before any commands, for going back through the history
+    start: Board,
+    // What the constraints said after the last command
+    deductions: Deductions,
+    // Simplified form of each constraint, by index
+    simplified: Vec<Simplified>,
+    // Apply deductions after every command
+    auto_apply: bool,
+    // The next command replaces this constraint.
+    // Only the finished edit goes in the history, as `edit N <constraint>`
+    editing: Option<usize>,
+    // Every command run on the game, (command, error)
+    history: Vec<(String, Option<String>)>,
+}
+
+// What a successful command did
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Effect {
+    Done,
+    // Deductions were applied to this many elements
+    Applied(usize),
+    // Automatic deductions were turned on or off
+    AutoApply(bool),
+    // A constraint is being edited, the next command replaces it
+    Editing(usize, String),
+    // The board written out in a format, for the front end to save
+    Exported(Format, String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandError {
+    pub message: String,
+    // Characters of the command that are wrong, and what is wrong with them
+    pub span: Option<(Range<usize>, String)>,
+    // The command can be fixed and sent again, so it should be given back to the user
+    pub retry: bool,
+}
+
+impl Game {
+    pub fn new(board: Board) -> Self {
+        let mut out = Self {
+            start: board.clone(),
+            board,
+            deductions: Deductions::default(),
+            simplified: Vec::new(),
+            auto_apply: false,
+            editing: None,
+            history: Vec::new(),
+        };
+        out.refresh();
+        out
+    }
+
+    // Rebuild a game from its commands, one per line
+    pub fn replay(board: Board, commands: &str) -> Self {
+        let mut out = Self::new(board);
+        for command in commands.lines().filter(|x| !x.trim().is_empty()) {
+            let _ = out.execute(command);
+        }
+        out.editing = None;
+        out
+    }
+
+    // The game as it was after the first `turn` commands of the history
+    pub fn at(&self, turn: usize) -> Self {
+        let mut out = Self::new(self.start.clone());
+        for (command, _) in self.history.iter().take(turn) {
+            let _ = out.execute(command);
+        }
+        out
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    pub fn deductions(&self) -> &Deductions {
+        &self.deductions
+    }
+
+    pub fn simplified(&self) -> &[Simplified] {
+        &self.simplified
+    }
+
+    pub fn auto_apply(&self) -> bool {
+        self.auto_apply
+    }
+
+    pub fn editing(&self) -> Option<usize> {
+        self.editing
+    }
+
+    pub fn history(&self) -> &[(String, Option<String>)] {
+        &self.history
+    }
+
+    // Stop editing without changing the constraint
+    pub fn cancel_edit(&mut self) -> bool {
+        self.editing.take().is_some()
+    }
+
+    pub fn execute(&mut self, command: &str) -> Result<Effect, CommandError> {
+        if let Some(index) = self.editing.take() {
+            let out = self.replace_constraint(index, command);
+            // Keep editing so the constraint can be fixed
+            if out.as_ref().is_err_and(|e| e.retry) {
+                self.editing = Some(index);
+            }
+            return self.finish(format!("edit {} {}", index + 1, command), out);
+        }
+
+        let out = self.run(command);
+        // Starting an edit changes nothing until it is finished
+        if let Ok(Effect::Editing(..)) = out {
+            return out;
+        }
+        self.finish(command.to_owned(), out)
+    }
+
+    // Run a command from another front end while this one may be editing.
+    // It goes beside the edit instead of into it, and can't start an edit of its own
+    pub fn execute_shared(&mut self, command: &str) -> Result<Effect, CommandError> {
+        let editing = self.editing.take();
+        let edited = editing.and_then(|x| self.board.constraints().get(x).cloned());
+
+        let out = self.execute(command);
+        if let Ok(Effect::Editing(..)) = out {
+            self.editing = editing;
+            return Err(CommandError::new(
+                "Only the terminal can start an edit, use 'edit N <constraint>'",
+            ));
+        }
+
+        // The edit is dropped if its constraint moved or changed
+        self.editing = editing.filter(|x| self.board.constraints().get(*x) == edited.as_ref());
+        out
+    }
+
+    // Update what the constraints say and record the command
+    fn finish<T>(
+        &mut self,
+        command: String,
+        out: Result<T, CommandError>,
+    ) -> Result<T, CommandError> {
+        self.refresh();
+        let error = out.as_ref().err().map(|x| x.message.clone());
+        self.history.push((command, error));
+        out
+    }
+
+    // Record a fact about an element without going through a text command
+    pub fn set_state(
+        &mut self,
+        id: &ElementIdentifier,
+        state: ElementState,
+    ) -> Result<Effect, CommandError> {
+        let out = match self.board.set_state(id, state) {
+            true => Ok(Effect::Done),
+            false => Err(CommandError::new("Unknown element")),
+        };
+        self.finish(format!("{}{}", id, state.as_char()), out)
+    }
+
+    // Add a constraint, even if the text would also be an element command.
+    // Returns the index it was added at
+    pub fn add_constraint(&mut self, raw: &str) -> Result<usize, CommandError> {
+        let out = self
+            .board
+            .parse(raw)
+            .and_then(|x| self.board.add_constraint(x))
+            .map_err(CommandError::retry);
+        self.finish(raw.to_owned(), out)
+    }
+
+    // Replay a written down game into the player model, see `transcript` for the format.
+    // Nothing is imported if any line is wrong. Returns the number of turns
+    pub fn import_transcript(&mut self, raw: &str) -> Result<usize, Vec<LineError>> {
+        let turns = transcript::parse(raw, self.board.elements())?;
+
+        // Played on a copy that only replaces this game if every command works
+        let mut game = self.clone();
+        let mut errors = Vec::new();
+        for turn in turns.iter() {
+            for command in turn.commands() {
+                // The same cards can be shown again, that says nothing new
+                let repeat = game
+                    .board
+                    .parse(&command)
+                    .map(|x| game.board.constraints().contains(&x))
+                    .unwrap_or(false);
+                if repeat {
+                    continue;
+                }
+
+                if let Err(e) = game.execute(&command) {
+                    errors.push((turn.line, e.message));
+                }
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        *self = game;
+        Ok(turns.len())
+    }
+
+    // The constraints (by index) that say something about an element and what they say
+    pub fn explain(&self, id: &ElementIdentifier) -> Vec<(usize, SolvedState)> {
+        self.board
+            .constraints()
+            .iter()
+            .enumerate()
+            .filter_map(|(i, x)| match self.deductions.solved.get(x) {
+                Some((element, state)) if element == id => Some((i, *state)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn run(&mut self, command: &str) -> Result<Effect, CommandError> {
+        if command == "apply" {
+            return match self.board.apply_deductions() {
+                0 => Err(CommandError::new("Nothing to apply")),
+                x => Ok(Effect::Applied(x)),
+            };
+        }
+
+        if command == "undo" {
+            return match self.board.undo() {
+                true => Ok(Effect::Done),
+                false => Err(CommandError::new("Nothing to undo")),
+            };
+        }
+
+        if command == "simplify" {
+            self.board.commit_simplified();
+            return Ok(Effect::Done);
+        }
+
+        if command == "auto" {
+            self.auto_apply = !self.auto_apply;
+            if !self.auto_apply {
+                self.board.clear_derived();
+            }
+            return Ok(Effect::AutoApply(self.auto_apply));
+        }
+
+        // export <format> [path], where to save it is left to the front end
+        if let Some(args) = command.strip_prefix("export ") {
+            let format = Format::from_name(args.split_whitespace().next().unwrap_or(""))
+                .ok_or_else(|| CommandError::new("Unknown format, use md, csv or json"))?;
+            return Ok(Effect::Exported(format, export::export(self, format)));
+        }
+
+        if let Some(players) = command.strip_prefix("players ") {
+            self.board
+                .set_players(players.split_whitespace().map(|x| x.to_owned()).collect());
+            return Ok(Effect::Done);
+        }
+
+        // `edit N` loads a constraint for editing, `edit N <constraint>` replaces it
+        if let Some(args) = command.strip_prefix("edit ") {
+            let (index, text) = match args.trim_start().split_once(' ') {
+                Some((index, text)) => (index, text.trim_start()),
+                None => (args.trim(), ""),
+            };
+            let index = index
+                .parse::<usize>()
+                .ok()
+                .and_then(|x| x.checked_sub(1))
+                .filter(|x| *x < self.board.constraints().len())
+                .ok_or(BoardError::Index)?;
+
+            if text.trim().is_empty() {
+                return self.start_edit(index);
+            }
+
+            // Point errors at the constraint within the whole command
+            let start = command.chars().count() - text.chars().count();
+            return self.replace_constraint(index, text).map_err(|mut e| {
+                if let Some((span, _)) = e.span.as_mut() {
+                    *span = span.start + start..span.end + start;
+                }
+                e
+            });
+        }
+
+        // Todo: move this someware else
+        if command.starts_with('r') {
+            let index = command
+                .chars()
+                .skip(1)
+                .take_while(|x| x.is_ascii_digit())
+                .collect::<String>()
+                .parse::<usize>()
+                .ok()
+                .and_then(|x| x.checked_sub(1))
+                .ok_or(BoardError::Index)?;
+
+            self.board.remove_constraint(index)?;
+            return Ok(Effect::Done);
+        }
+
+        match self.board.process_action(command) {
+            None => Ok(Effect::Done),
+            Some(action_error) => match self.board.parse(command) {
+                Ok(x) => {
+                    self.board.add_constraint(x)?;
+                    Ok(Effect::Done)
+                }
+                // Only report the constraint error if it wasn't an element command
+                Err(e) => Err(match action_error {
+                    Some(x) => CommandError::new(x),
+                    None => CommandError::retry(e),
+                }),
+            },
+        }
+    }
+
+    // Load a constraint for editing, the next command will replace it
+    fn start_edit(&mut self, index: usize) -> Result<Effect, CommandError> {
+        let text = self
+            .board
+            .constraints()
+            .get(index)
+            .ok_or(BoardError::Index)?
+            .to_string();
+
+        self.editing = Some(index);
+        Ok(Effect::Editing(index, text))
+    }
+
+    // Swap out a constraint, keeping its position.
+    // If the new one is invalid the old one is kept
+    fn replace_constraint(&mut self, index: usize, raw: &str) -> Result<Effect, CommandError> {
+        let error = match self.board.parse(raw) {
+            Ok(x) => match self.board.replace_constraint(index, x) {
+                Ok(_) => return Ok(Effect::Done),
+                Err(e) => e,
+            },
+            Err(e) => e,
+        };
+
+        // The constraint was removed while it was being edited
+        if let BoardError::Index = error {
+            return Err(CommandError::new("Constraint no longer exists"));
+        }
+
+        Err(CommandError::retry(error))
+    }
+
+    fn refresh(&mut self) {
+        // Applied deductions are worked out again so they never outlive what they came from,
+        // new ones are only added with auto
+        if self.auto_apply {
+            self.board.apply_deductions();
+        } else if !self.board.derivations().is_empty() {
+            self.board.recheck_derivations();
+        }
+
+        self.simplified = self.board.simplified();
+        self.deductions = self.board.deductions();
+    }
+}
+
+impl CommandError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            span: None,
+            retry: false,
+        }
+    }
+
+    // An error in the command itself, pointing at the problem if it can
+    fn retry(error: BoardError) -> Self {
+        let span = match &error {
+            BoardError::Parse(x) => Some((x.span.clone(), x.kind.to_string())),
+            _ => None,
+        };
+
+        Self {
+            message: error.to_string(),
+            span,
+            retry: true,
+        }
+    }
+}
+
+impl From<BoardError> for CommandError {
+    fn from(error: BoardError) -> Self {
+        Self::new(error.to_string())
+    }
+}
+
+impl Display for CommandError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::element::{Elements, Knowledge};
+
+    fn game() -> Game {
+        let mut out = Game::new(Board::new(Elements::numbered(6)));
+        out.execute("players Alice Bob Carol").unwrap();
+        out
+    }
+
+    fn deduced(game: &Game, raw: &str) -> Option<Option<SolvedState>> {
+        let id = game.board().elements().parse_id(raw).unwrap();
+        game.deductions().states.get(&id).copied()
+    }
+
+    #[test]
+    fn transcript_turns_become_constraints() {
+        let mut game = game();
+        let turns = game
+            .import_transcript(
+                "Alice suggests l1 p1 w1, Bob passes, Carol shows\n\
+                 Bob suggests l2 p1 w1, Carol passes, Alice shows w1\n\
+                 Alice suggests l3 p2 w2, nobody shows\n\
+                 Alice suggests w1 l1 p1, Bob passes, Carol shows",
+            )
+            .unwrap();
+        // The repeated turn says nothing new
+        assert_eq!(turns, 4);
+
+        let constraints = game
+            .board()
+            .constraints()
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            constraints,
+            ["w1@3 | l1@3 | p1@3", "l3 | l3@1", "p2 | p2@1", "w2 | w2@1"]
+        );
+
+        // Carol passed on p1 and w1 so showed l1
+        assert_eq!(deduced(&game, "l1"), Some(Some(SolvedState::Dismissed)));
+        assert_eq!(deduced(&game, "l3"), None);
+
+        // Once Alice is known to not hold l3 nobody else can
+        game.execute("l3!1").unwrap();
+        assert_eq!(deduced(&game, "l3"), Some(Some(SolvedState::Confirmed)));
+    }
+
+    #[test]
+    fn wrong_transcript_imports_nothing() {
+        let mut game = game();
+        let errors = game
+            .import_transcript(
+                "Alice suggests l1 p1 w1, Bob passes, Carol shows\n\
+                 Dave suggests l1 p1 w1, nobody shows",
+            )
+            .unwrap_err();
+        assert_eq!(errors, [(2, "Unknown player 'Dave'".to_owned())]);
+        assert!(game.board().constraints().is_empty());
+        assert_eq!(game.history().len(), 1);
+    }
+
+    // What a replay has to get the same
+    fn summary(game: &Game) -> (Vec<String>, Vec<ElementState>) {
+        let elements = game.board().elements();
+        (
+            game.board()
+                .constraints()
+                .iter()
+                .map(|x| x.to_string())
+                .collect(),
+            elements
+                .ids()
+                .iter()
+                .filter_map(|x| elements.get_state(x))
+                .collect(),
+        )
+    }
+
+    fn commands(game: &Game) -> String {
+        game.history()
+            .iter()
+            .filter(|x| x.1.is_none())
+            .map(|x| x.0.clone())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn edits_are_one_command() {
+        let mut game = game();
+        game.execute("w1 | w2").unwrap();
+        game.execute("l1 | l2").unwrap();
+
+        // Cancelled, so the next command is just a command
+        assert!(matches!(game.execute("edit 1"), Ok(Effect::Editing(0, _))));
+        assert!(game.cancel_edit());
+        game.execute("w3c").unwrap();
+
+        // A mistake keeps the edit going
+        game.execute("edit 2").unwrap();
+        let error = game.execute("l1 | l9").unwrap_err();
+        assert_eq!(error.span.unwrap().0, 6..7);
+        assert_eq!(game.editing(), Some(1));
+        game.execute("l1 | l3").unwrap();
+
+        let error = game.execute("edit 1 w1 | w9").unwrap_err();
+        assert_eq!(error.span.unwrap().0, 13..14);
+        assert_eq!(game.editing(), None);
+
+        assert_eq!(
+            commands(&game),
+            "players Alice Bob Carol\nw1 | w2\nl1 | l2\nw3c\nedit 2 l1 | l3"
+        );
+        let replayed = Game::replay(game.start.clone(), &commands(&game));
+        assert_eq!(summary(&replayed), summary(&game));
+        assert_eq!(summary(&game).0, ["w1 | w2", "l1 | l3"]);
+    }
+
+    #[test]
+    fn deduced_holders_are_applied() {
+        let mut game = game();
+        for command in ["w1@3 | l1@3 | p1@3", "p1!3 w1!3"] {
+            game.execute(command).unwrap();
+        }
+        let l1 = game.board().elements().parse_id("l1").unwrap();
+        assert_eq!(game.deductions().holders[&(l1, 2)], Some(true));
+        assert_eq!(
+            game.board().elements().player_knowledge(&l1, 2),
+            Knowledge::Unknown
+        );
+
+        game.execute("auto").unwrap();
+        let elements = game.board().elements();
+        assert_eq!(elements.player_knowledge(&l1, 2), Knowledge::Deduced(true));
+        assert_eq!(elements.player_knowledge(&l1, 0), Knowledge::Deduced(false));
+        assert_eq!(elements.envelope_knowledge(&l1), Knowledge::Deduced(false));
+
+        // Gone with the constraint it came from
+        game.execute("r1").unwrap();
+        let elements = game.board().elements();
+        assert_eq!(elements.player_knowledge(&l1, 2), Knowledge::Unknown);
+        assert_eq!(elements.envelope_knowledge(&l1), Knowledge::Unknown);
+    }
+
+    #[test]
+    fn applied_deductions_follow_their_constraints() {
+        let mut game = game();
+        for command in ["w1 | w2", "w1d"] {
+            game.execute(command).unwrap();
+        }
+        assert_eq!(game.execute("apply"), Ok(Effect::Applied(1)));
+        let state = |game: &Game, raw: &str| {
+            let id = game.board().elements().parse_id(raw).unwrap();
+            game.board().elements().envelope_knowledge(&id)
+        };
+        assert_eq!(state(&game, "w2"), Knowledge::Deduced(true));
+
+        // A single apply doesn't take in later deductions
+        for command in ["l1 | l2", "l1d"] {
+            game.execute(command).unwrap();
+        }
+        assert_eq!(state(&game, "l2"), Knowledge::Unknown);
+        assert_eq!(deduced(&game, "l2"), Some(Some(SolvedState::Confirmed)));
+        assert_eq!(state(&game, "w2"), Knowledge::Deduced(true));
+
+        // But drops what no longer follows
+        game.execute("w1u").unwrap();
+        assert_eq!(state(&game, "w2"), Knowledge::Unknown);
+        assert!(game.board().derivations().is_empty());
+
+        // Auto takes in everything, and keeps doing so
+        game.execute("auto").unwrap();
+        assert_eq!(state(&game, "l2"), Knowledge::Deduced(true));
+        game.execute("w1d").unwrap();
+        assert_eq!(state(&game, "w2"), Knowledge::Deduced(true));
+    }
+
+    #[test]
+    fn undoing_players_puts_the_holders_back() {
+        let mut game = game();
+        for command in ["w1d", "l1@3 | l2@3", "players Alice Carol"] {
+            game.execute(command).unwrap();
+        }
+        assert_eq!(summary(&game).0, ["l1@2 | l2@2"]);
+
+        game.execute("undo").unwrap();
+        assert_eq!(game.board().elements().players, ["Alice", "Bob", "Carol"]);
+        assert_eq!(summary(&game).0, ["l1@3 | l2@3"]);
+
+        // The element command before it is still its own step
+        game.execute("undo").unwrap();
+        assert_eq!(
+            summary(&game)
+                .1
+                .iter()
+                .filter(|x| **x != ElementState::Unknown)
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn shared_commands_leave_the_edit_alone() {
+        let mut game = game();
+        game.execute("w1 | w2").unwrap();
+        game.execute("l1 | l2").unwrap();
+        game.execute("edit 2").unwrap();
+
+        // Runs as its own command, not as the new constraint
+        game.execute_shared("w3c").unwrap();
+        assert_eq!(game.editing(), Some(1));
+        assert!(game.execute_shared("edit 1").is_err());
+        assert_eq!(game.editing(), Some(1));
+        game.execute_shared("edit 1 w1 | w4").unwrap();
+        assert_eq!(game.editing(), Some(1));
+
+        // Finishing the edit here still works
+        game.execute("l1 | l3").unwrap();
+        assert_eq!(summary(&game).0, ["w1 | w4", "l1 | l3"]);
+
+        // Removing the constraint being edited ends the edit
+        game.execute("edit 2").unwrap();
+        game.execute_shared("r1").unwrap();
+        assert_eq!(game.editing(), None);
+        assert_eq!(summary(&game).0, ["l1 | l3"]);
+    }
+
+    #[test]
+    fn timeline_matches_the_game() {
+        let mut game = game();
+        let mut seen = vec![(game.history().len(), summary(&game))];
+        for command in [
+            "w1 | w2", "edit 1", "esc", "w3c", "edit 1", "w1 | w4", "w2d",
+        ] {
+            match command {
+                "esc" => assert!(game.cancel_edit()),
+                x => {
+                    game.execute(x).unwrap();
+                }
+            }
+            seen.push((game.history().len(), summary(&game)));
+        }
+
+        assert_eq!(game.history().len(), 5);
+        for (turn, expected) in seen {
+            let past = game.at(turn);
+            assert_eq!(summary(&past), expected, "turn {}", turn);
+            assert_eq!(past.editing(), None);
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use clue_solver::element::ElementState;
+use crossterm::event::{
+    Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
+
+use crate::app::App;
+
+// Handle a terminal event.
+// Returns true if the ui needs to be redrawn
+pub fn handle(app: &App, event: Event) -> bool {
+    match event {
+        Event::Key(key) if key.kind != KeyEventKind::Release => handle_key(app, key),
+        Event::Mouse(mouse) => handle_mouse(app, mouse),
+        Event::Resize(..) => true,
+        _ => false,
+    }
+}
+
+fn handle_key(app: &App, key: KeyEvent) -> bool {
+    // Any edit moves the text away from the error marker
+    if matches!(
+        key.code,
+        KeyCode::Char(_) | KeyCode::Backspace | KeyCode::Enter | KeyCode::Esc
+    ) {
+        *app.input_error.write() = None;
+    }
+
+    // Undo and remove would be taken as the new constraint while editing
+    let editing = app.game().editing().is_some();
+    let control = key.modifiers.contains(KeyModifiers::CONTROL);
+    match key.code {
+        KeyCode::Char('c') if control => app.exit(),
+        KeyCode::Char('z') if control && editing => return false,
+        KeyCode::Char('z') if control => {
+            app.execute_command("undo".to_owned());
+            app.refresh_constraints();
+        }
+        KeyCode::Char(x) => app.input.write().push(x),
+        KeyCode::Backspace => {
+            app.input.write().pop();
+        }
+        KeyCode::Enter => {
+            let command = app.input.write().drain(..).collect::<String>();
+            if command.trim().is_empty() {
+                return edit_selected(app);
+            }
+
+            app.execute_command(command);
+            app.refresh_constraints();
+        }
+        KeyCode::F(2) => app.toggle_view(),
+        KeyCode::Up => move_selection(app, -1),
+        KeyCode::Down => move_selection(app, 1),
+        KeyCode::Left => return app.step_timeline(-1),
+        KeyCode::Right => return app.step_timeline(1),
+        KeyCode::Esc => {
+            app.close_timeline();
+            app.cancel_edit();
+            app.input.write().clear();
+            app.table_mut().selected_constraint = None;
+        }
+        KeyCode::Delete if !editing => return remove_selected(app),
+        _ => return false,
+    }
+
+    true
+}
+
+// Left click cycles an element through the states the user can set, right click resets it.
+// Set straight on the game so a constraint being edited is left alone.
+fn handle_mouse(app: &App, mouse: MouseEvent) -> bool {
+    let button = match mouse.kind {
+        MouseEventKind::Down(x @ (MouseButton::Left | MouseButton::Right)) => x,
+        _ => return false,
+    };
+
+    let id = match app
+        .element_hitboxes
+        .read()
+        .iter()
+        .find(|((row, start, end), _)| *row == mouse.row && (*start..=*end).contains(&mouse.column))
+    {
+        Some((_, id)) => *id,
+        None => return false,
+    };
+
+    let state = match button {
+        // A derived state was never entered, so cycle on from unknown
+        MouseButton::Left => match app.game().board().elements().get(&id) {
+            Some(x) if !x.derived => x.state.cycle(),
+            _ => ElementState::Unknown.cycle(),
+        },
+        _ => ElementState::Unknown,
+    };
+
+    app.set_element_state(id, state);
+    app.refresh_constraints();
+    true
+}
+
+// Move the constraint selection, starting from the bottom when going up
+fn move_selection(app: &App, delta: isize) {
+    let len = app.game().board().constraints().len();
+    let mut table = app.table_mut();
+    let selected = &mut table.selected_constraint;
+    if len == 0 {
+        *selected = None;
+        return;
+    }
+
+    *selected = Some(match *selected {
+        Some(x) => (x as isize + delta).clamp(0, len as isize - 1) as usize,
+        None if delta < 0 => len - 1,
+        None => 0,
+    });
+}
+
+fn remove_selected(app: &App) -> bool {
+    let selected = match app.table().selected_constraint {
+        Some(x) => x,
+        None => return false,
+    };
+
+    app.execute_command(format!("r{}", selected + 1));
+    app.refresh_constraints();
+    true
+}
+
+fn edit_selected(app: &App) -> bool {
+    let selected = match app.table().selected_constraint {
+        Some(x) => x,
+        None => return false,
+    };
+
+    app.execute_command(format!("edit {}", selected + 1));
+    true
+}
@@ -0,0 +1,13 @@
+// Solving logic for Clue, shared by the terminal app and any other tools
+pub mod board;
+pub mod constraints;
+pub mod element;
+pub mod export;
+pub mod game;
+pub mod protocol;
+pub mod server;
+pub mod simulation;
+pub mod transcript;
+
+pub use board::{Board, BoardError, Deductions};
+pub use game::{CommandError, Effect, Game};
@@ -1,31 +1,148 @@
-use std::{io::stdin, sync::Arc};
+use std::{
+    env,
+    io::stdout,
+    net::{SocketAddr, TcpListener, UdpSocket},
+    panic,
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+};
 
-use app::App;
+use app::{App, Remote};
+use benchmark::Options;
+use clue_solver::{server, simulation::strategy, Board};
 use crossterm::{
+    event::{self, DisableMouseCapture, EnableMouseCapture},
     execute,
-    terminal::{EnterAlternateScreen, SetTitle},
+    terminal::{
+        disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen, SetTitle,
+    },
 };
-use element::Elements;
+use theme::Theme;
 
 mod app;
-mod constraints;
-mod element;
+mod benchmark;
+mod input;
+mod stdio;
+mod theme;
 mod ui;
 
 fn main() {
-    let elements = Elements::load("./elements.toml").unwrap();
-    let app = Arc::new(App::new(elements));
+    let args = env::args().collect::<Vec<_>>();
+    // The value after a flag, like `--players 4`
+    let option = |name: &str| {
+        args.iter()
+            .position(|x| x == name)
+            .and_then(|i| args.get(i + 1))
+            .map(|x| x.as_str())
+    };
+
+    // --edition path, for playing with a different set of cards
+    let board = Board::load(option("--edition").unwrap_or("./elements.toml")).unwrap();
+    if args.iter().any(|x| x == "--json") {
+        return stdio::run(board);
+    }
+
+    // --simulate [games] [--players N] [--strategy name,...] [--seed N]
+    if args.iter().any(|x| x == "--simulate") {
+        let games = option("--simulate")
+            .and_then(|x| x.parse().ok())
+            .unwrap_or(100);
+        let players = option("--players")
+            .and_then(|x| x.parse::<usize>().ok())
+            .unwrap_or(4)
+            .max(2);
+        // Strategies are given per player, the last one fills in the rest
+        let mut strategies = option("--strategy")
+            .unwrap_or("solver")
+            .split(',')
+            .map(|x| x.to_owned())
+            .collect::<Vec<_>>();
+        if let Some(x) = strategies.iter().find(|x| strategy::from_name(x).is_none()) {
+            eprintln!(
+                "Unknown strategy '{}', use {} or risky-<name> to take chances on accusations",
+                x,
+                strategy::NAMES.join(", ")
+            );
+            return;
+        }
+        let last = strategies.last().unwrap().clone();
+        strategies.resize(players, last);
+
+        return benchmark::run(
+            board,
+            Options {
+                games,
+                strategies,
+                seed: option("--seed").and_then(|x| x.parse().ok()),
+            },
+        );
+    }
+
+    let theme = Theme::load("./theme.toml").unwrap_or_default();
+    let app = Arc::new(App::new(board, theme));
+    let mut title = "Clue Solver".to_owned();
+
+    // --serve [address], for other devices to follow the game while it is played here.
+    // The default only takes this machine, phones need `--serve 0.0.0.0:7878`
+    if let Some(i) = args.iter().position(|x| x == "--serve") {
+        let addr = args
+            .get(i + 1)
+            .filter(|x| !x.starts_with("--"))
+            .map(|x| x.as_str())
+            .unwrap_or("127.0.0.1:7878");
+        let listener = TcpListener::bind(addr).unwrap();
+        title = format!(
+            "Clue Solver - http://{}",
+            reachable(listener.local_addr().unwrap())
+        );
+
+        let clients = server::spawn(listener, Arc::new(Remote(app.clone())));
+        *app.clients.write() = Some(clients);
+    }
+
+    // Put the terminal back before the panic message is printed
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let _ = disable_raw_mode();
+        let _ = execute!(stdout(), DisableMouseCapture, LeaveAlternateScreen);
+        default_hook(info);
+    }));
+
+    enable_raw_mode().unwrap();
     execute!(
         app.stdout.lock(),
         EnterAlternateScreen,
-        SetTitle("Clue Solver")
+        EnableMouseCapture,
+        SetTitle(title)
     )
     .unwrap();
 
     ui::draw(app.clone());
-    for line in stdin().lines().map(Result::unwrap) {
-        app.execute_command(line);
-        app.refresh_constraints();
-        ui::draw(app.clone());
+    loop {
+        // Changes from the server are drawn here too, so only this thread draws
+        let mut redraw = app.redraw.swap(false, Ordering::Relaxed);
+        if event::poll(Duration::from_millis(100)).unwrap() {
+            redraw |= input::handle(&app, event::read().unwrap());
+        }
+
+        if redraw {
+            ui::draw(app.clone());
+        }
     }
 }
+
+// The address other devices can open, so a listener on every interface shows the LAN address
+fn reachable(addr: SocketAddr) -> SocketAddr {
+    if !addr.ip().is_unspecified() {
+        return addr;
+    }
+
+    // Connecting a UDP socket sends nothing, it only picks the interface a route would use
+    UdpSocket::bind("0.0.0.0:0")
+        .and_then(|x| {
+            x.connect("192.0.2.1:80")?;
+            x.local_addr()
+        })
+        .map(|x| SocketAddr::new(x.ip(), addr.port()))
+        .unwrap_or(addr)
+}
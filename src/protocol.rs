@@ -0,0 +1,237 @@
+// JSON requests and responses for driving a `Game` from another program.
+// One request per line, every request gets one response with the same `id`:
+// {"id": 1, "type": "command", "command": "w2d"}
+// {"id": 2, "type": "set_state", "element": "w2", "state": "dismissed"}
+// {"id": 3, "type": "add_constraint", "constraint": "w1 | l3"}
+// {"id": 4, "type": "state"}
+// {"id": 5, "type": "explain", "element": "w1"}
+// {"id": 6, "type": "import", "transcript": "Ann suggests l2 p3 w1, Bob shows"}
+// Requests that change the game are followed by a `changed` event with the new state.
+
+use serde_json::{json, Value};
+
+use crate::{
+    constraints::simplify::Simplified,
+    element::ElementState,
+    game::{CommandError, Effect, Game},
+};
+
+// Handle one request, returning the messages to send back
+pub fn handle(game: &mut Game, request: &Value) -> Vec<Value> {
+    respond(game, request, false)
+}
+
+// Parse and handle a line of text
+pub fn handle_line(game: &mut Game, line: &str) -> Vec<Value> {
+    match serde_json::from_str::<Value>(line) {
+        Ok(x) => handle(game, &x),
+        Err(e) => vec![invalid_json(e)],
+    }
+}
+
+// Like `handle_line` for a game that is also played somewhere else,
+// commands are run with `Game::execute_shared` so that front end's edit is left alone
+pub fn handle_shared_line(game: &mut Game, line: &str) -> Vec<Value> {
+    match serde_json::from_str::<Value>(line) {
+        Ok(x) => respond(game, &x, true),
+        Err(e) => vec![invalid_json(e)],
+    }
+}
+
+fn respond(game: &mut Game, request: &Value, shared: bool) -> Vec<Value> {
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let result = match run(game, request, shared) {
+        Ok(x) => x,
+        Err(e) => return vec![error(id, e)],
+    };
+
+    let mut out = vec![json!({ "id": id, "ok": true, "result": result.0 })];
+    if result.1 {
+        out.push(json!({ "event": "changed", "state": state(game) }));
+    }
+    out
+}
+
+fn invalid_json(e: serde_json::Error) -> Value {
+    error(
+        Value::Null,
+        CommandError::new(format!("Invalid JSON: {}", e)),
+    )
+}
+
+// The result of a request and if it changed the game
+fn run(game: &mut Game, request: &Value, shared: bool) -> Result<(Value, bool), CommandError> {
+    let field = |name: &str| {
+        request
+            .get(name)
+            .and_then(|x| x.as_str())
+            .ok_or_else(|| CommandError::new(format!("Missing field '{}'", name)))
+    };
+    let element = |name: &str| {
+        let raw = field(name)?;
+        game.board()
+            .elements()
+            .parse_id(raw)
+            .ok_or_else(|| CommandError::new(format!("Unknown element '{}'", raw)))
+    };
+
+    match field("type")? {
+        "command" => {
+            let command = field("command")?;
+            let effect = match shared {
+                true => game.execute_shared(command)?,
+                false => game.execute(command)?,
+            };
+            Ok((effect_json(effect), true))
+        }
+        "set_state" => {
+            let id = element("element")?;
+            let state = ElementState::from_name(field("state")?)
+                .ok_or_else(|| CommandError::new("Unknown state"))?;
+            game.set_state(&id, state)?;
+            Ok((Value::Null, true))
+        }
+        "add_constraint" => {
+            let index = game.add_constraint(field("constraint")?)?;
+            Ok((json!({ "index": index + 1 }), true))
+        }
+        "import" => match game.import_transcript(field("transcript")?) {
+            Ok(x) => Ok((json!({ "turns": x }), true)),
+            Err(errors) => Err(CommandError::new(
+                errors
+                    .iter()
+                    .map(|(line, message)| format!("Line {}: {}", line, message))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            )),
+        },
+        "state" => Ok((state(game), false)),
+        "explain" => {
+            let id = element("element")?;
+            let constraints = game.board().constraints();
+            let sources = game
+                .explain(&id)
+                .into_iter()
+                .map(|(i, state)| {
+                    json!({
+                        "constraint": i + 1,
+                        "text": constraints[i].to_string(),
+                        "state": state.name(),
+                    })
+                })
+                .collect::<Vec<_>>();
+            Ok((
+                json!({ "element": id.to_string(), "sources": sources }),
+                false,
+            ))
+        }
+        x => Err(CommandError::new(format!("Unknown request type '{}'", x))),
+    }
+}
+
+// Everything a front end needs to show the game
+pub fn state(game: &Game) -> Value {
+    let elements = game.board().elements();
+    let deductions = game.deductions();
+
+    let cards = elements
+        .ids()
+        .into_iter()
+        .filter_map(|id| {
+            let element = elements.get(&id)?;
+            let deduction = match deductions.states.get(&id) {
+                Some(Some(x)) => json!(x.name()),
+                Some(None) => json!("conflict"),
+                None => Value::Null,
+            };
+
+            Some(json!({
+                "id": id.to_string(),
+                "type": id.element_type.name(),
+                "name": element.name,
+                "state": element.state.name(),
+                "derived": element.derived,
+                "owner": element.owner.map(|x| x + 1),
+                "deduction": deduction,
+            }))
+        })
+        .collect::<Vec<_>>();
+
+    let constraints = game
+        .board()
+        .constraints()
+        .iter()
+        .zip(game.simplified())
+        .enumerate()
+        .map(|(i, (constraint, simplified))| {
+            let solved = deductions
+                .solved
+                .get(constraint)
+                .map(|(id, state)| json!({ "element": id.to_string(), "state": state.name() }));
+
+            json!({
+                "index": i + 1,
+                "text": constraint.to_string(),
+                "solved": solved,
+                "simplified": simplified_json(simplified),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let conflicts = deductions
+        .conflicts
+        .iter()
+        .map(|(id, found)| {
+            let sources = found
+                .iter()
+                .map(|(i, state)| json!({ "constraint": i + 1, "state": state.name() }))
+                .collect::<Vec<_>>();
+            json!({ "element": id.to_string(), "sources": sources })
+        })
+        .collect::<Vec<_>>();
+
+    json!({
+        "elements": cards,
+        "players": elements.players,
+        "constraints": constraints,
+        "conflicts": conflicts,
+        "auto_apply": game.auto_apply(),
+        "editing": game.editing().map(|x| x + 1),
+    })
+}
+
+fn simplified_json(simplified: &Simplified) -> Value {
+    match simplified {
+        Simplified::Satisfied => json!({ "kind": "satisfied" }),
+        Simplified::Violated => json!({ "kind": "violated" }),
+        Simplified::Subsumed(x) => json!({ "kind": "subsumed", "by": x + 1 }),
+        Simplified::Reduced(x) => json!({
+            "kind": "reduced",
+            "elements": x.iter().map(|x| x.to_string()).collect::<Vec<_>>(),
+        }),
+        Simplified::Unchanged => json!({ "kind": "unchanged" }),
+    }
+}
+
+fn effect_json(effect: Effect) -> Value {
+    match effect {
+        Effect::Done => json!({ "kind": "done" }),
+        Effect::Applied(x) => json!({ "kind": "applied", "count": x }),
+        Effect::AutoApply(x) => json!({ "kind": "auto_apply", "enabled": x }),
+        Effect::Editing(index, text) => {
+            json!({ "kind": "editing", "index": index + 1, "text": text })
+        }
+        Effect::Exported(format, text) => {
+            json!({ "kind": "exported", "format": format.extension(), "text": text })
+        }
+    }
+}
+
+fn error(id: Value, error: CommandError) -> Value {
+    json!({
+        "id": id,
+        "ok": false,
+        "error": error.message,
+        "span": error.span.map(|(span, _)| [span.start, span.end]),
+    })
+}
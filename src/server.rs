@@ -0,0 +1,313 @@
+use std::{
+    io::{BufRead, BufReader, ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use tungstenite::Message;
+
+use crate::{game::Game, protocol};
+
+// How long a websocket waits for a message before checking for updates to push
+const POLL: Duration = Duration::from_millis(100);
+
+// Largest request body taken, anything bigger gets a 413
+const MAX_BODY: usize = 64 * 1024;
+
+// Largest request line and headers taken, anything bigger gets a 431
+const MAX_HEAD: u64 = 8 * 1024;
+
+// Whatever holds the game being served
+pub trait Host: Send + Sync {
+    // Handle a `protocol` request, returning the messages it produced
+    fn request(&self, raw: &str) -> Vec<Value>;
+
+    // The state new websocket clients start from
+    fn state(&self) -> Value;
+}
+
+// Websocket connections waiting for `changed` events
+#[derive(Default)]
+pub struct Clients(Mutex<Vec<Sender<String>>>);
+
+struct Shared {
+    host: Arc<dyn Host>,
+    clients: Arc<Clients>,
+}
+
+// A game on its own, for serving without a terminal
+impl Host for Mutex<Game> {
+    fn request(&self, raw: &str) -> Vec<Value> {
+        protocol::handle_shared_line(&mut self.lock(), raw)
+    }
+
+    fn state(&self) -> Value {
+        protocol::state(&self.lock())
+    }
+}
+
+impl Clients {
+    // Push the new state to every websocket, forgetting ones that have gone
+    pub fn changed(&self, state: Value) {
+        self.send(&json!({ "event": "changed", "state": state }));
+    }
+
+    fn send(&self, event: &Value) {
+        let event = event.to_string();
+        self.0.lock().retain(|x| x.send(event.clone()).is_ok());
+    }
+
+    // Get every event from now on
+    fn follow(&self) -> Receiver<String> {
+        let (sender, receiver) = mpsc::channel();
+        self.0.lock().push(sender);
+        receiver
+    }
+}
+
+// Serve `host` from a background thread until the program exits.
+// GET /state        current state as JSON
+// POST /request     a `protocol` request as the body, answered with its response
+// GET /ws           websocket, takes `protocol` requests and pushes every change
+// GET /             a page that shows the state and follows along
+// Returns the websocket clients, for pushing changes made elsewhere
+pub fn spawn(listener: TcpListener, host: Arc<dyn Host>) -> Arc<Clients> {
+    let clients = Arc::new(Clients::default());
+    let shared = Arc::new(Shared {
+        host,
+        clients: clients.clone(),
+    });
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let shared = shared.clone();
+            thread::spawn(move || match is_websocket(&stream) {
+                true => websocket(stream, &shared),
+                false => http(stream, &shared),
+            });
+        }
+    });
+
+    clients
+}
+
+// Look at the request headers without taking them off the stream
+fn is_websocket(stream: &TcpStream) -> bool {
+    let mut buf = [0; 4096];
+    for _ in 0..50 {
+        let read = match stream.peek(&mut buf) {
+            Ok(0) | Err(_) => return false,
+            Ok(x) => x,
+        };
+
+        let head = String::from_utf8_lossy(&buf[..read]).to_ascii_lowercase();
+        if head.contains("\r\n\r\n") || read == buf.len() {
+            return head.contains("upgrade: websocket");
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    false
+}
+
+// Run a request against the game, sending any events to every websocket.
+// Returns the messages for whoever sent it
+fn request(shared: &Shared, raw: &str) -> Vec<Value> {
+    let (events, responses) = shared
+        .host
+        .request(raw)
+        .into_iter()
+        .partition::<Vec<_>, _>(|x| x.get("event").is_some());
+
+    for event in events {
+        shared.clients.send(&event);
+    }
+
+    responses
+}
+
+fn websocket(stream: TcpStream, shared: &Shared) {
+    let mut socket = match tungstenite::accept(stream) {
+        Ok(x) => x,
+        Err(_) => return,
+    };
+    if socket.get_ref().set_read_timeout(Some(POLL)).is_err() {
+        return;
+    }
+
+    // Following before taking the state means no change can land between the two.
+    // The host is never asked while the clients are locked as it locks the game first
+    let receiver = shared.clients.follow();
+    let state = json!({ "event": "changed", "state": shared.host.state() });
+    if socket.send(Message::Text(state.to_string())).is_err() {
+        return;
+    }
+
+    loop {
+        match socket.read() {
+            Ok(Message::Text(x)) => {
+                for response in request(shared, &x) {
+                    if socket.send(Message::Text(response.to_string())).is_err() {
+                        return;
+                    }
+                }
+            }
+            Ok(Message::Close(_)) => return,
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(e))
+                if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(_) => return,
+        }
+
+        while let Ok(event) = receiver.try_recv() {
+            if socket.send(Message::Text(event)).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+fn http(stream: TcpStream, shared: &Shared) {
+    // Capped so a client can't hold the thread with one endless line
+    let mut reader = BufReader::new((&stream).take(MAX_HEAD));
+    let mut request_line = String::new();
+    let mut content_length = 0;
+    let mut head_too_large = false;
+    for i in 0.. {
+        let mut line = String::new();
+        if reader.read_line(&mut line).is_err() {
+            return;
+        }
+        // Running out mid line is either the cap or the client hanging up
+        if !line.ends_with('\n') {
+            if reader.get_ref().limit() > 0 {
+                return;
+            }
+            head_too_large = true;
+            break;
+        }
+
+        if i == 0 {
+            request_line = line;
+            continue;
+        }
+        if line.trim().is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+
+    reader.get_mut().set_limit(MAX_BODY as u64);
+
+    let mut parts = request_line.split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next()) {
+        _ if head_too_large => (
+            "431 Request Header Fields Too Large",
+            "text/plain",
+            "Request headers too large".to_owned(),
+        ),
+        _ if content_length > MAX_BODY => (
+            "413 Payload Too Large",
+            "text/plain",
+            "Request too large".to_owned(),
+        ),
+        (Some("GET"), Some("/")) => ("200 OK", "text/html", PAGE.to_owned()),
+        (Some("GET"), Some("/state")) => (
+            "200 OK",
+            "application/json",
+            shared.host.state().to_string(),
+        ),
+        (Some("POST"), Some("/request")) => {
+            let mut body = vec![0; content_length];
+            if reader.read_exact(&mut body).is_err() {
+                return;
+            }
+
+            let responses = request(shared, &String::from_utf8_lossy(&body));
+            let response = responses.into_iter().next().unwrap_or(Value::Null);
+            ("200 OK", "application/json", response.to_string())
+        }
+        _ => ("404 Not Found", "text/plain", "Not found".to_owned()),
+    };
+
+    let _ = write!(
+        &stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+}
+
+// Read only view for phones around the table
+const PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Clue Solver</title>
+<style>
+body { font-family: monospace; background: #111; color: #ddd; }
+.confirmed { color: #4c4; } .dismissed { color: #c44; }
+.derived { font-style: italic; } .conflict { color: #fc0; }
+</style>
+</head>
+<body>
+<div id="elements"></div>
+<h3>Constraints</h3>
+<ol id="constraints"></ol>
+<script>
+const symbols = { confirmed: "✓", dismissed: "✗", unknown: "?", any: "~", conflict: "!" };
+const socket = new WebSocket(`ws://${location.host}/ws`);
+// Names come from the deck and players, so they only ever go in as text
+const add = (parent, tag, text, className) => {
+  const node = document.createElement(tag);
+  node.textContent = text;
+  if (className) node.className = className;
+  parent.appendChild(node);
+  return node;
+};
+
+socket.onmessage = (message) => {
+  const data = JSON.parse(message.data);
+  if (data.event !== "changed") return;
+
+  const elements = document.getElementById("elements");
+  elements.replaceChildren();
+  let type = "";
+  for (const element of data.state.elements) {
+    if (element.type !== type) {
+      type = element.type;
+      add(elements, "h3", type);
+    }
+    const row = add(elements, "div", `${symbols[element.state]} ${element.id} ${element.name}`,
+      `${element.state}${element.derived ? " derived" : ""}`);
+    if (element.deduction) {
+      row.append(" ");
+      add(row, "span", symbols[element.deduction], element.deduction);
+    }
+  }
+
+  const constraints = document.getElementById("constraints");
+  constraints.replaceChildren();
+  for (const x of data.state.constraints) {
+    add(constraints, "li", `${x.text}${x.solved ? ` => ${x.solved.element} ${symbols[x.solved.state]}` : ""}`);
+  }
+};
+</script>
+</body>
+</html>
+"#;
@@ -0,0 +1,726 @@
+// Random games played out to see how quickly the solver finds the answer.
+// Every player keeps their own `Game` and only learns what they would at a real table:
+// their hand, who passed, and the card shown when they made the suggestion.
+// Those go in as facts, everything else a turn says goes in as constraints
+// and the answer comes from what the solver deduces.
+
+use rand::{seq::SliceRandom, RngCore};
+
+use crate::{
+    board::Board,
+    element::{ElementIdentifier, ElementState, ElementType, Elements},
+    game::Game,
+    transcript::Turn,
+};
+
+pub use strategy::Strategy;
+
+// Give up on a game that goes on this long
+pub const MAX_TURNS: usize = 500;
+
+const TYPES: [ElementType; 3] = [
+    ElementType::Location,
+    ElementType::Person,
+    ElementType::Weapon,
+];
+
+// The cards as they were dealt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Deal {
+    // Location, person and weapon
+    pub envelope: [ElementIdentifier; 3],
+    // Cards held by each player
+    pub hands: Vec<Vec<ElementIdentifier>>,
+}
+
+// What a player can see when making a choice
+pub struct View<'a> {
+    // Their own notes
+    pub game: &'a Game,
+    pub hand: &'a [ElementIdentifier],
+}
+
+// How a simulated game went
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outcome {
+    pub deal: Deal,
+    // Turns taken before the first player's solver knew the envelope,
+    // None if the game ended first
+    pub solved: Option<usize>,
+    // The solver's answer matched the envelope
+    pub correct: bool,
+    // The player who accused correctly and the turn they did it on
+    pub winner: Option<(usize, usize)>,
+    // Players out of the game after a wrong accusation
+    pub eliminated: Vec<usize>,
+    // Turns played before the game ended
+    pub turns: usize,
+}
+
+impl<'a> View<'a> {
+    fn new(games: &'a [Game], deal: &'a Deal, player: usize) -> Self {
+        Self {
+            game: &games[player],
+            hand: &deal.hands[player],
+        }
+    }
+}
+
+impl Deal {
+    // Pick the envelope, then deal the rest out one at a time starting with the first player
+    pub fn random(elements: &Elements, players: usize, rng: &mut dyn RngCore) -> Self {
+        let ids = elements.ids();
+        let mut envelope = Vec::new();
+        let mut rest = Vec::new();
+        for element_type in TYPES {
+            let mut cards = ids
+                .iter()
+                .filter(|x| x.element_type == element_type)
+                .copied()
+                .collect::<Vec<_>>();
+            cards.shuffle(rng);
+            envelope.push(cards.remove(0));
+            rest.extend(cards);
+        }
+
+        rest.shuffle(rng);
+        let mut hands = vec![Vec::new(); players];
+        for (i, card) in rest.into_iter().enumerate() {
+            hands[i % players].push(card);
+        }
+
+        Self {
+            envelope: [envelope[0], envelope[1], envelope[2]],
+            hands,
+        }
+    }
+
+    pub fn holder(&self, id: &ElementIdentifier) -> Option<usize> {
+        self.hands.iter().position(|x| x.contains(id))
+    }
+}
+
+// Cards of a type that could still be in the envelope, going by the solver's deductions
+pub fn candidates(game: &Game, element_type: ElementType) -> Vec<ElementIdentifier> {
+    let elements = game.board().elements();
+    let deductions = game.deductions();
+    let cards = elements
+        .ids()
+        .into_iter()
+        .filter(|x| x.element_type == element_type)
+        .map(|x| {
+            let state = match elements.get_state(&x) {
+                Some(ElementState::Unknown) | None => deductions
+                    .states
+                    .get(&x)
+                    .copied()
+                    .flatten()
+                    .and_then(|x| x.as_state())
+                    .unwrap_or(ElementState::Unknown),
+                Some(x) => x,
+            };
+            (x, state)
+        })
+        .collect::<Vec<_>>();
+
+    if let Some(x) = cards.iter().find(|x| x.1 == ElementState::Confirmed) {
+        return vec![x.0];
+    }
+
+    cards
+        .into_iter()
+        .filter(|x| x.1 != ElementState::Dismissed)
+        .map(|x| x.0)
+        .collect()
+}
+
+// The envelope as far as the solver knows, once every type is down to one card
+pub fn solution(game: &Game) -> Option<[ElementIdentifier; 3]> {
+    let mut out = Vec::new();
+    for element_type in TYPES {
+        match candidates(game, element_type)[..] {
+            [x] => out.push(x),
+            _ => return None,
+        }
+    }
+
+    Some([out[0], out[1], out[2]])
+}
+
+// Deal a game for one player per strategy and play it until someone accuses correctly,
+// everyone is out, or `MAX_TURNS` is reached
+pub fn play(board: &Board, strategies: &mut [Box<dyn Strategy>], rng: &mut dyn RngCore) -> Outcome {
+    let players = strategies.len();
+    let deal = Deal::random(board.elements(), players, rng);
+
+    let mut board = board.clone();
+    board.set_players((1..=players).map(|x| format!("P{}", x)).collect());
+    let ids = board.elements().ids();
+    let mut games = (0..players)
+        .map(|player| {
+            let mut game = Game::new(board.clone());
+            let _ = game.execute("auto");
+
+            // Every card is in the envelope or someone's hand
+            for id in ids.iter() {
+                let places = (1..=players)
+                    .map(|x| format!("{}@{}", id, x))
+                    .collect::<Vec<_>>();
+                let _ = game.add_constraint(&format!("{} | {}", id, places.join(" | ")));
+            }
+
+            let hand = ids
+                .iter()
+                .map(|id| match deal.hands[player].contains(id) {
+                    true => format!("{}@{}", id, player + 1),
+                    false => format!("{}!{}", id, player + 1),
+                })
+                .collect::<Vec<_>>();
+            let _ = game.execute(&hand.join(" "));
+            game
+        })
+        .collect::<Vec<_>>();
+
+    let mut outcome = Outcome {
+        deal,
+        solved: None,
+        correct: false,
+        winner: None,
+        eliminated: Vec::new(),
+        turns: MAX_TURNS,
+    };
+    let deal = &outcome.deal;
+
+    for turn in 0..MAX_TURNS {
+        if outcome.solved.is_none() {
+            if let Some(answer) = solution(&games[0]) {
+                outcome.solved = Some(turn);
+                outcome.correct = answer == deal.envelope;
+            }
+        }
+
+        // Players who are out still show cards, but don't get a turn
+        let suggester = turn % players;
+        if outcome.eliminated.contains(&suggester) {
+            if outcome.eliminated.len() == players {
+                outcome.turns = turn;
+                break;
+            }
+            continue;
+        }
+
+        let cards = strategies[suggester].suggest(&View::new(&games, deal, suggester), rng);
+        let mut played = Turn {
+            line: 0,
+            suggester,
+            cards,
+            passed: Vec::new(),
+            shown: None,
+            nobody: false,
+        };
+
+        // Ask around the table until someone has one of the cards
+        for player in (1..players).map(|x| (suggester + x) % players) {
+            let held = played
+                .cards
+                .iter()
+                .filter(|x| deal.holder(x) == Some(player))
+                .copied()
+                .collect::<Vec<_>>();
+            if held.is_empty() {
+                played.passed.push(player);
+                continue;
+            }
+
+            let card =
+                strategies[player].show(&View::new(&games, deal, player), suggester, &held, rng);
+            played.shown = Some((player, Some(card)));
+            break;
+        }
+        played.nobody = played.shown.is_none();
+
+        // Only the suggester sees which card it was
+        let hidden = Turn {
+            shown: played.shown.map(|(player, _)| (player, None)),
+            ..played.clone()
+        };
+        for (player, game) in games.iter_mut().enumerate() {
+            let turn = match player == suggester {
+                true => &played,
+                false => &hidden,
+            };
+            for command in turn.commands() {
+                let _ = game.execute(&command);
+            }
+        }
+
+        match strategies[suggester].accuse(&View::new(&games, deal, suggester), rng) {
+            Some(x) if x == deal.envelope => {
+                outcome.winner = Some((suggester, turn));
+                outcome.turns = turn + 1;
+                break;
+            }
+            Some(_) => outcome.eliminated.push(suggester),
+            None => {}
+        }
+    }
+
+    // The last turn can still have given the first player the answer
+    if outcome.solved.is_none() {
+        if let Some(answer) = solution(&games[0]) {
+            outcome.solved = Some(outcome.turns);
+            outcome.correct = answer == outcome.deal.envelope;
+        }
+    }
+
+    outcome
+}
+
+pub mod strategy {
+    use hashbrown::HashMap;
+    use rand::{seq::SliceRandom, RngCore};
+
+    use super::{candidates, solution, View, TYPES};
+    use crate::element::{ElementIdentifier, ElementType, Knowledge};
+
+    // How a simulated player plays.
+    // Each player gets their own for every game, so it can remember what it did
+    pub trait Strategy {
+        // A location, person and weapon to suggest
+        fn suggest(&mut self, view: &View, rng: &mut dyn RngCore) -> Vec<ElementIdentifier>;
+
+        // Which of `held` to show `suggester`, there is always at least one
+        fn show(
+            &mut self,
+            _view: &View,
+            _suggester: usize,
+            held: &[ElementIdentifier],
+            rng: &mut dyn RngCore,
+        ) -> ElementIdentifier {
+            *held.choose(rng).unwrap()
+        }
+
+        // The envelope to accuse after a suggestion, a wrong accusation is out of the game.
+        // Waits for the solver to be sure by default
+        fn accuse(
+            &mut self,
+            view: &View,
+            _rng: &mut dyn RngCore,
+        ) -> Option<[ElementIdentifier; 3]> {
+            solution(view.game)
+        }
+    }
+
+    // Any of them can be given as `risky-<name>` to take chances on accusations
+    pub const NAMES: [&str; 3] = ["random", "greedy", "solver"];
+
+    pub fn from_name(name: &str) -> Option<Box<dyn Strategy>> {
+        if let Some(x) = name.strip_prefix("risky-") {
+            return Some(Box::new(Risky(from_name(x)?)));
+        }
+
+        Some(match name {
+            "random" => Box::new(Random),
+            "greedy" => Box::new(Greedy::default()),
+            "solver" => Box::new(Solver::default()),
+            _ => return None,
+        })
+    }
+
+    // Suggests and shows anything
+    pub struct Random;
+
+    // Suggests the cards it knows least about
+    #[derive(Default)]
+    pub struct Greedy {
+        shown: Shown,
+    }
+
+    // Plays like another strategy but takes a chance on an accusation
+    // once one answer is at least as likely as all the others together
+    pub struct Risky(pub Box<dyn Strategy>);
+
+    // Only asks about cards the solver's deductions haven't ruled out, filling in with
+    // its own cards once a type is known, and accuses when the solver is certain
+    #[derive(Default)]
+    pub struct Solver {
+        shown: Shown,
+    }
+
+    // Cards shown to each player, so the same ones can be shown again
+    #[derive(Default)]
+    struct Shown(HashMap<usize, Vec<ElementIdentifier>>);
+
+    fn cards(element_type: ElementType, view: &View) -> Vec<ElementIdentifier> {
+        view.game
+            .board()
+            .elements()
+            .ids()
+            .into_iter()
+            .filter(|x| x.element_type == element_type)
+            .collect()
+    }
+
+    // Places a card could still be, the envelope and every player who might hold it
+    fn uncertainty(id: &ElementIdentifier, view: &View) -> usize {
+        let elements = view.game.board().elements();
+        let players = (0..elements.players.len())
+            .map(|x| elements.player_knowledge(id, x))
+            .collect::<Vec<_>>();
+        if players
+            .iter()
+            .any(|x| matches!(x, Knowledge::Known(true) | Knowledge::Deduced(true)))
+        {
+            return 0;
+        }
+
+        let envelope = elements.envelope_knowledge(id) == Knowledge::Unknown;
+        players.iter().filter(|x| **x == Knowledge::Unknown).count() + envelope as usize
+    }
+
+    // One of the cards that the player knows least about
+    fn least_known(
+        cards: &[ElementIdentifier],
+        view: &View,
+        rng: &mut dyn RngCore,
+    ) -> Option<ElementIdentifier> {
+        let scores = cards
+            .iter()
+            .map(|x| (*x, uncertainty(x, view)))
+            .collect::<Vec<_>>();
+        let best = scores.iter().map(|x| x.1).max()?;
+        let best = scores
+            .into_iter()
+            .filter(|x| x.1 == best)
+            .map(|x| x.0)
+            .collect::<Vec<_>>();
+        best.choose(rng).copied()
+    }
+
+    impl Shown {
+        // Prefer a card the suggester has already seen, then one that others have seen
+        fn choose(
+            &mut self,
+            suggester: usize,
+            held: &[ElementIdentifier],
+            rng: &mut dyn RngCore,
+        ) -> ElementIdentifier {
+            let seen = |id: &ElementIdentifier| self.0.values().filter(|x| x.contains(id)).count();
+            let card = match held
+                .iter()
+                .find(|x| self.0.get(&suggester).is_some_and(|y| y.contains(x)))
+            {
+                Some(x) => *x,
+                None => {
+                    let most = held.iter().map(seen).max().unwrap_or(0);
+                    let options = held
+                        .iter()
+                        .filter(|x| seen(x) == most)
+                        .copied()
+                        .collect::<Vec<_>>();
+                    *options.choose(rng).unwrap()
+                }
+            };
+
+            let shown = self.0.entry(suggester).or_default();
+            if !shown.contains(&card) {
+                shown.push(card);
+            }
+            card
+        }
+    }
+
+    impl Strategy for Random {
+        fn suggest(&mut self, view: &View, rng: &mut dyn RngCore) -> Vec<ElementIdentifier> {
+            TYPES
+                .iter()
+                .map(|x| *cards(*x, view).choose(rng).unwrap())
+                .collect()
+        }
+    }
+
+    impl Strategy for Greedy {
+        fn suggest(&mut self, view: &View, rng: &mut dyn RngCore) -> Vec<ElementIdentifier> {
+            TYPES
+                .iter()
+                .map(|x| least_known(&cards(*x, view), view, rng).unwrap())
+                .collect()
+        }
+
+        fn show(
+            &mut self,
+            _view: &View,
+            suggester: usize,
+            held: &[ElementIdentifier],
+            rng: &mut dyn RngCore,
+        ) -> ElementIdentifier {
+            self.shown.choose(suggester, held, rng)
+        }
+    }
+
+    impl Strategy for Risky {
+        fn suggest(&mut self, view: &View, rng: &mut dyn RngCore) -> Vec<ElementIdentifier> {
+            self.0.suggest(view, rng)
+        }
+
+        fn show(
+            &mut self,
+            view: &View,
+            suggester: usize,
+            held: &[ElementIdentifier],
+            rng: &mut dyn RngCore,
+        ) -> ElementIdentifier {
+            self.0.show(view, suggester, held, rng)
+        }
+
+        fn accuse(&mut self, view: &View, rng: &mut dyn RngCore) -> Option<[ElementIdentifier; 3]> {
+            let candidates = TYPES
+                .iter()
+                .map(|x| candidates(view.game, *x))
+                .collect::<Vec<_>>();
+            let chance = candidates
+                .iter()
+                .map(|x| 1.0 / x.len() as f64)
+                .product::<f64>();
+            if chance < 0.5 {
+                return None;
+            }
+
+            Some([
+                *candidates[0].choose(rng)?,
+                *candidates[1].choose(rng)?,
+                *candidates[2].choose(rng)?,
+            ])
+        }
+    }
+
+    impl Strategy for Solver {
+        fn suggest(&mut self, view: &View, rng: &mut dyn RngCore) -> Vec<ElementIdentifier> {
+            TYPES
+                .iter()
+                .map(|element_type| {
+                    let candidates = candidates(view.game, *element_type);
+                    if let [answer] = candidates[..] {
+                        // Nobody can show an own card, so the answers are about the rest
+                        let own = view
+                            .hand
+                            .iter()
+                            .filter(|x| x.element_type == *element_type)
+                            .copied()
+                            .collect::<Vec<_>>();
+                        return own.choose(rng).copied().unwrap_or(answer);
+                    }
+
+                    // Nothing left means the notes contradict each other, ask about anything
+                    match candidates.choose(rng) {
+                        Some(x) => *x,
+                        None => *cards(*element_type, view).choose(rng).unwrap(),
+                    }
+                })
+                .collect()
+        }
+
+        fn show(
+            &mut self,
+            _view: &View,
+            suggester: usize,
+            held: &[ElementIdentifier],
+            rng: &mut dyn RngCore,
+        ) -> ElementIdentifier {
+            self.shown.choose(suggester, held, rng)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::SmallRng, SeedableRng};
+
+    use super::*;
+
+    fn board() -> Board {
+        Board::new(Elements::numbered(6))
+    }
+
+    #[test]
+    fn games_end_with_the_envelope() {
+        let mut rng = SmallRng::seed_from_u64(7);
+        for _ in 0..5 {
+            let mut strategies = (0..3)
+                .map(|_| strategy::from_name("random").unwrap())
+                .collect::<Vec<_>>();
+            let outcome = play(&board(), &mut strategies, &mut rng);
+
+            // Accusations wait for the solver, so the first one is right
+            assert!(outcome.winner.is_some(), "{:?}", outcome);
+            assert!(outcome.eliminated.is_empty());
+            // The first player may not have known yet, but was never wrong
+            assert!(outcome.solved.is_none() || outcome.correct);
+        }
+    }
+
+    // Always accuses with a card from its own hand, so is always wrong
+    struct Wrong;
+
+    impl Strategy for Wrong {
+        fn suggest(&mut self, view: &View, rng: &mut dyn RngCore) -> Vec<ElementIdentifier> {
+            strategy::Random.suggest(view, rng)
+        }
+
+        fn accuse(&mut self, view: &View, rng: &mut dyn RngCore) -> Option<[ElementIdentifier; 3]> {
+            let mut out = self.suggest(view, rng);
+            let own = view.hand[0];
+            out[TYPES.iter().position(|x| *x == own.element_type)?] = own;
+            Some([out[0], out[1], out[2]])
+        }
+    }
+
+    #[test]
+    fn game_ends_once_everyone_is_out() {
+        let mut rng = SmallRng::seed_from_u64(5);
+        let mut strategies = (0..3)
+            .map(|_| Box::new(Wrong) as Box<dyn Strategy>)
+            .collect::<Vec<_>>();
+        let outcome = play(&board(), &mut strategies, &mut rng);
+
+        assert_eq!(outcome.winner, None);
+        assert_eq!(outcome.eliminated, [0, 1, 2]);
+        // The first player's turn comes round again and finds nobody left
+        assert_eq!(outcome.turns, 3);
+        assert!(outcome.solved.is_none_or(|x| x <= 3));
+    }
+
+    // The first of three players' notes after some commands
+    fn notes(commands: &[&str]) -> Game {
+        let mut game = Game::new(board());
+        game.execute("players A B C").unwrap();
+        for command in commands {
+            game.execute(command).unwrap();
+        }
+        game
+    }
+
+    fn ids(game: &Game, raw: &str) -> Vec<ElementIdentifier> {
+        raw.split_whitespace()
+            .map(|x| game.board().elements().parse_id(x).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn random_suggests_one_of_each_type() {
+        let mut rng = SmallRng::seed_from_u64(1);
+        let game = notes(&[]);
+        let view = View {
+            game: &game,
+            hand: &[],
+        };
+
+        for _ in 0..20 {
+            let cards = strategy::Random.suggest(&view, &mut rng);
+            let types = cards.iter().map(|x| x.element_type).collect::<Vec<_>>();
+            assert_eq!(types, TYPES);
+        }
+        assert_eq!(strategy::Random.accuse(&view, &mut rng), None);
+    }
+
+    #[test]
+    fn greedy_asks_about_the_least_known() {
+        let mut rng = SmallRng::seed_from_u64(2);
+        let mut greedy = strategy::Greedy::default();
+
+        // Cards whose holder is known tell it nothing
+        let game = notes(&["l1-5@2 p1-5@3 w2-6@2"]);
+        let view = View {
+            game: &game,
+            hand: &[],
+        };
+        for _ in 0..20 {
+            assert_eq!(greedy.suggest(&view, &mut rng), ids(&game, "l6 p6 w1"));
+        }
+
+        // Waits for the solver before accusing
+        let game = notes(&["l2-6d p2-6d w3-6d"]);
+        let view = View {
+            game: &game,
+            hand: &[],
+        };
+        assert_eq!(greedy.accuse(&view, &mut rng), None);
+    }
+
+    #[test]
+    fn risky_takes_even_chances() {
+        let mut rng = SmallRng::seed_from_u64(2);
+        let mut risky = strategy::from_name("risky-greedy").unwrap();
+
+        let game = notes(&["l2-6d p2-6d w4-6d"]);
+        let view = View {
+            game: &game,
+            hand: &[],
+        };
+        assert_eq!(risky.accuse(&view, &mut rng), None);
+
+        let game = notes(&["l2-6d p2-6d w3-6d"]);
+        let view = View {
+            game: &game,
+            hand: &[],
+        };
+        let [location, person, weapon] = risky.accuse(&view, &mut rng).unwrap();
+        assert_eq!([location, person], ids(&game, "l1 p1")[..]);
+        assert!(ids(&game, "w1 w2").contains(&weapon));
+
+        assert!(strategy::from_name("risky-nothing").is_none());
+    }
+
+    #[test]
+    fn solver_follows_the_deductions() {
+        let mut rng = SmallRng::seed_from_u64(3);
+        let mut solver = strategy::Solver::default();
+
+        // The weapon is w2 only because a constraint says so
+        let game = notes(&["l2-6d", "w1 | w2", "w1d w3-6d"]);
+        let hand = ids(&game, "l4");
+        let view = View {
+            game: &game,
+            hand: &hand,
+        };
+        for _ in 0..20 {
+            let cards = solver.suggest(&view, &mut rng);
+            // Known types are filled in with its own card, or the answer without one
+            assert_eq!(cards[0], hand[0]);
+            assert_eq!(cards[2], ids(&game, "w2")[0]);
+        }
+        assert_eq!(solver.accuse(&view, &mut rng), None);
+
+        let game = notes(&["l2-6d", "w1 | w2", "w1d w3-6d", "p1-5d"]);
+        let view = View {
+            game: &game,
+            hand: &hand,
+        };
+        assert_eq!(
+            solver.accuse(&view, &mut rng),
+            Some([
+                ids(&game, "l1")[0],
+                ids(&game, "p6")[0],
+                ids(&game, "w2")[0]
+            ])
+        );
+    }
+
+    #[test]
+    fn solver_asks_about_candidates() {
+        let mut rng = SmallRng::seed_from_u64(4);
+        let mut solver = strategy::Solver::default();
+        let game = notes(&["l1-3d p2,4,6d w1-5@2"]);
+        let view = View {
+            game: &game,
+            hand: &[],
+        };
+
+        for _ in 0..20 {
+            let cards = solver.suggest(&view, &mut rng);
+            assert!(ids(&game, "l4 l5 l6").contains(&cards[0]));
+            assert!(ids(&game, "p1 p3 p5").contains(&cards[1]));
+            assert_eq!(cards[2], ids(&game, "w6")[0]);
+        }
+    }
+}
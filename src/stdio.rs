@@ -0,0 +1,102 @@
+use std::io::{self, BufRead, Write};
+
+use clue_solver::{protocol, Board, Game};
+
+// Answer JSON requests from stdin until it closes, see `protocol` for the format
+pub fn run(board: Board) {
+    let mut game = Game::new(board);
+    // The other end going away is the normal way to finish
+    let _ = serve(&mut game, io::stdin().lock(), io::stdout().lock());
+}
+
+// Answer each line of `input`, stopping when either side closes
+fn serve(game: &mut Game, input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        for message in protocol::handle_line(game, &line) {
+            writeln!(output, "{}", message)?;
+        }
+        output.flush()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use clue_solver::element::Elements;
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn responses(input: &str) -> Vec<Value> {
+        let board = Board::new(Elements::numbered(6));
+
+        let mut output = Vec::new();
+        serve(&mut Game::new(board), input.as_bytes(), &mut output).unwrap();
+        String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|x| serde_json::from_str(x).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn command_gets_a_response_and_an_event() {
+        let out = responses("{\"id\": 1, \"type\": \"command\", \"command\": \"w2d\"}\n");
+        assert_eq!(out.len(), 2);
+        assert_eq!(
+            out[0],
+            json!({ "id": 1, "ok": true, "result": { "kind": "done" } })
+        );
+        assert_eq!(out[1]["event"], "changed");
+        assert_eq!(out[1]["state"]["elements"][13]["id"], "w2");
+        assert_eq!(out[1]["state"]["elements"][13]["state"], "dismissed");
+    }
+
+    #[test]
+    fn error_points_at_the_problem() {
+        let out = responses("\n{\"id\": \"a\", \"type\": \"command\", \"command\": \"w1 | w9\"}\n");
+        assert_eq!(
+            out,
+            [json!({
+                "id": "a",
+                "ok": false,
+                "error": "Index out of range (1-6) at column 7",
+                "span": [6, 7],
+            })]
+        );
+    }
+
+    #[test]
+    fn invalid_json_is_answered() {
+        let out = responses("not json\n");
+        assert_eq!(out[0]["ok"], false);
+        assert_eq!(out[0]["id"], Value::Null);
+    }
+
+    #[test]
+    fn closed_output_stops_cleanly() {
+        struct Closed;
+        impl Write for Closed {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::BrokenPipe.into())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut game = Game::new(Board::new(Elements::new(
+            vec!["L".to_owned()],
+            vec!["P".to_owned()],
+            vec!["W".to_owned()],
+        )));
+        let input = "{\"id\": 1, \"type\": \"state\"}\n".as_bytes();
+        assert!(serve(&mut game, input, Closed).is_err());
+    }
+}
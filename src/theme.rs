@@ -0,0 +1,185 @@
+use std::{fs, path::Path};
+
+use clue_solver::element::ElementState;
+use crossterm::style::Color;
+use toml::Value;
+
+#[derive(Debug, Clone)]
+pub struct Theme {
+    pub confirmed: Color,
+    pub dismissed: Color,
+    pub unknown: Color,
+    // States worked out by the solver rather than entered
+    pub deduced_confirmed: Color,
+    pub deduced_dismissed: Color,
+    // Borders, hints and unsolvable constraints
+    pub muted: Color,
+    pub ok: Color,
+    pub error: Color,
+
+    pub confirmed_symbol: char,
+    pub dismissed_symbol: char,
+    pub unknown_symbol: char,
+    // The solver found the element doesnt matter for a constraint
+    pub any_symbol: char,
+    // Constraints disagree about the element
+    pub conflict_symbol: char,
+}
+
+impl Theme {
+    pub fn preset(name: &str) -> Option<Self> {
+        Some(match name {
+            "default" => Self::default(),
+            "colorblind" => Self::colorblind(),
+            _ => return None,
+        })
+    }
+
+    // Okabe-Ito palette, blue and orange instead of green and red
+    pub fn colorblind() -> Self {
+        Self {
+            confirmed: Color::Rgb {
+                r: 0,
+                g: 114,
+                b: 178,
+            },
+            dismissed: Color::Rgb {
+                r: 230,
+                g: 159,
+                b: 0,
+            },
+            unknown: Color::Reset,
+            deduced_confirmed: Color::Rgb {
+                r: 86,
+                g: 180,
+                b: 233,
+            },
+            deduced_dismissed: Color::Rgb {
+                r: 213,
+                g: 94,
+                b: 0,
+            },
+            muted: Color::DarkGrey,
+            ok: Color::Rgb {
+                r: 0,
+                g: 114,
+                b: 178,
+            },
+            error: Color::Rgb {
+                r: 230,
+                g: 159,
+                b: 0,
+            },
+            ..Self::default()
+        }
+    }
+
+    pub fn state_color(&self, state: ElementState) -> Color {
+        match state {
+            ElementState::Confirmed => self.confirmed,
+            ElementState::Dismissed => self.dismissed,
+            ElementState::Unknown => self.unknown,
+        }
+    }
+
+    pub fn state_symbol(&self, state: ElementState) -> char {
+        match state {
+            ElementState::Confirmed => self.confirmed_symbol,
+            ElementState::Dismissed => self.dismissed_symbol,
+            ElementState::Unknown => self.unknown_symbol,
+        }
+    }
+
+    // Load a theme file like:
+    // preset = "colorblind"
+    // [colors]
+    // confirmed = "#0072b2"
+    // [symbols]
+    // unknown = " "
+    pub fn load(path: impl AsRef<Path>) -> Option<Self> {
+        let raw = fs::read_to_string(path).ok()?;
+        let toml = toml::from_str::<Value>(&raw).ok()?;
+
+        let mut theme = match toml.get("preset").and_then(|x| x.as_str()) {
+            Some(x) => Self::preset(x)?,
+            None => Self::default(),
+        };
+
+        let color = |name: &str| {
+            toml.get("colors")?
+                .get(name)?
+                .as_str()
+                .and_then(parse_color)
+        };
+        let colors = [
+            ("confirmed", &mut theme.confirmed),
+            ("dismissed", &mut theme.dismissed),
+            ("unknown", &mut theme.unknown),
+            ("deduced_confirmed", &mut theme.deduced_confirmed),
+            ("deduced_dismissed", &mut theme.deduced_dismissed),
+            ("muted", &mut theme.muted),
+            ("ok", &mut theme.ok),
+            ("error", &mut theme.error),
+        ];
+        for (name, value) in colors {
+            if let Some(x) = color(name) {
+                *value = x;
+            }
+        }
+
+        let symbol = |name: &str| toml.get("symbols")?.get(name)?.as_str()?.chars().next();
+        let symbols = [
+            ("confirmed", &mut theme.confirmed_symbol),
+            ("dismissed", &mut theme.dismissed_symbol),
+            ("unknown", &mut theme.unknown_symbol),
+            ("any", &mut theme.any_symbol),
+            ("conflict", &mut theme.conflict_symbol),
+        ];
+        for (name, value) in symbols {
+            if let Some(x) = symbol(name) {
+                *value = x;
+            }
+        }
+
+        Some(theme)
+    }
+}
+
+// Accepts crossterm color names ("dark_red") or hex ("#ff8800")
+fn parse_color(raw: &str) -> Option<Color> {
+    if let Some(hex) = raw.strip_prefix('#') {
+        if hex.len() != 6 {
+            return None;
+        }
+
+        let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+        return Some(Color::Rgb {
+            r: channel(0)?,
+            g: channel(2)?,
+            b: channel(4)?,
+        });
+    }
+
+    Color::try_from(raw).ok()
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            confirmed: Color::Green,
+            dismissed: Color::Red,
+            unknown: Color::Reset,
+            deduced_confirmed: Color::Cyan,
+            deduced_dismissed: Color::Magenta,
+            muted: Color::DarkGrey,
+            ok: Color::Green,
+            error: Color::Red,
+
+            confirmed_symbol: '✓',
+            dismissed_symbol: '✗',
+            unknown_symbol: '?',
+            any_symbol: '~',
+            conflict_symbol: '!',
+        }
+    }
+}
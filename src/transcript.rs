@@ -0,0 +1,176 @@
+// Turns written down during a game, one per line:
+// Alice suggests l2 p3 w1, Bob passes, Carol shows w1
+// Alice suggests l4 p1 w2, Bob shows
+// Bob suggests l1 p1 w1, nobody shows
+// Blank lines and lines starting with # are skipped.
+// Players are matched by name, case insensitively.
+
+use crate::element::{ElementIdentifier, ElementType, Elements};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Turn {
+    // Line in the transcript, starting at 1
+    pub line: usize,
+    pub suggester: usize,
+    pub cards: Vec<ElementIdentifier>,
+    // Players who had none of the cards
+    pub passed: Vec<usize>,
+    // Who showed a card, and which one if it was seen
+    pub shown: Option<(usize, Option<ElementIdentifier>)>,
+    // Went all the way round without anyone showing
+    pub nobody: bool,
+}
+
+// An error with the line it was found on
+pub type LineError = (usize, String);
+
+// Read every turn, or every line that is wrong
+pub fn parse(raw: &str, elements: &Elements) -> Result<Vec<Turn>, Vec<LineError>> {
+    let mut turns = Vec::new();
+    let mut errors = Vec::new();
+
+    for (i, line) in raw.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        match parse_turn(line, elements) {
+            Ok(mut x) => {
+                x.line = i + 1;
+                turns.push(x);
+            }
+            Err(e) => errors.push((i + 1, e)),
+        }
+    }
+
+    match errors.is_empty() {
+        true => Ok(turns),
+        false => Err(errors),
+    }
+}
+
+fn parse_turn(line: &str, elements: &Elements) -> Result<Turn, String> {
+    let player = |name: &str| {
+        elements
+            .players
+            .iter()
+            .position(|x| x.eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("Unknown player '{}'", name))
+    };
+    let card = |raw: &str| {
+        elements
+            .parse_id(raw)
+            .ok_or_else(|| format!("Unknown element '{}'", raw))
+    };
+
+    let mut clauses = line
+        .split(',')
+        .map(|x| x.split_whitespace().collect::<Vec<_>>());
+    let suggestion = clauses.next().unwrap_or_default();
+    let (suggester, cards) = match suggestion[..] {
+        [name, "suggests", ref cards @ ..] => (player(name)?, cards),
+        _ => return Err("Expected '<player> suggests <cards>'".to_owned()),
+    };
+
+    let cards = cards
+        .iter()
+        .map(|x| card(x))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut types = cards.iter().map(|x| x.element_type).collect::<Vec<_>>();
+    types.sort();
+    if types
+        != [
+            ElementType::Weapon,
+            ElementType::Location,
+            ElementType::Person,
+        ]
+    {
+        return Err("A suggestion needs a location, a person and a weapon".to_owned());
+    }
+
+    let mut turn = Turn {
+        line: 0,
+        suggester,
+        cards,
+        passed: Vec::new(),
+        shown: None,
+        nobody: false,
+    };
+
+    for clause in clauses {
+        if turn.shown.is_some() || turn.nobody {
+            return Err("Nothing can follow the end of a turn".to_owned());
+        }
+
+        match clause[..] {
+            ["nobody", "shows"] => turn.nobody = true,
+            [name, "passes"] => turn.passed.push(player(name)?),
+            [name, "shows"] => turn.shown = Some((player(name)?, None)),
+            [name, "shows", raw] => {
+                let shown = card(raw)?;
+                if !turn.cards.contains(&shown) {
+                    return Err(format!("{} was not suggested", raw));
+                }
+                turn.shown = Some((player(name)?, Some(shown)));
+            }
+            _ => {
+                return Err(format!(
+                    "Expected '<player> passes' or '<player> shows [card]', found '{}'",
+                    clause.join(" ")
+                ))
+            }
+        }
+    }
+
+    // Everyone else went without showing anything
+    if turn.nobody {
+        turn.passed = (0..elements.players.len())
+            .filter(|x| *x != turn.suggester)
+            .collect();
+    }
+
+    Ok(turn)
+}
+
+impl Turn {
+    // The commands that record this turn: who passed and whose card was seen as facts,
+    // what can't be seen as constraints
+    pub fn commands(&self) -> Vec<String> {
+        let mut out = Vec::new();
+
+        let passes = self
+            .passed
+            .iter()
+            .flat_map(|player| {
+                self.cards
+                    .iter()
+                    .map(move |x| format!("{}!{}", x, player + 1))
+            })
+            .collect::<Vec<_>>();
+        if !passes.is_empty() {
+            out.push(passes.join(" "));
+        }
+
+        match self.shown {
+            Some((player, Some(card))) => out.push(format!("{}@{}", card, player + 1)),
+            // They hold at least one of the cards
+            Some((player, None)) => out.push(
+                self.cards
+                    .iter()
+                    .map(|x| format!("{}@{}", x, player + 1))
+                    .collect::<Vec<_>>()
+                    .join(" | "),
+            ),
+            // Nobody else could show, so each card is in the envelope or the suggester's hand
+            None if self.nobody => out.extend(
+                self.cards
+                    .iter()
+                    .map(|x| format!("{} | {}@{}", x, x, self.suggester + 1)),
+            ),
+            None => {}
+        }
+
+        out
+    }
+}
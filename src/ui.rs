@@ -4,99 +4,340 @@ use std::{
     sync::Arc,
 };
 
+use clue_solver::element::{ElementIdentifier, ElementState};
 use crossterm::{
     cursor::MoveTo,
     queue,
-    style::{ContentStyle, Print, PrintStyledContent, StyledContent},
+    style::{ContentStyle, PrintStyledContent, StyledContent},
     terminal::{Clear, ClearType},
     QueueableCommand,
 };
 
-use crate::{app::App, element::ElementState};
+use crate::{
+    app::{App, Table, View},
+    theme::Theme,
+};
+
+pub use layout::Hitbox;
 
 type Lines = Vec<Line>;
 
+// Only called from the main thread, other threads ask for a redraw through `App::redraw`
 pub fn draw(app: Arc<App>) {
+    let mut layout = layout::Layout::from_terminal();
+
+    // One guard for the whole frame, a second read could wait behind a writer forever
+    let tables = app.tables.read();
+    let table = &tables[*app.current.read()];
+    let tabs = tabs::get(&app, &tables);
+    if tabs.is_some() {
+        layout.top = 1;
+    }
+
+    let elements = match *app.view.read() {
+        View::List => elements::get(&app, table.shown(), layout.width),
+        View::Grid => grid::get(&app, table.shown(), layout.width),
+    };
+    let console_width = layout.remaining_width(&[&elements]);
+    let console = console::get(&app, table, console_width);
+    drop(tables);
+    let input_cursor = console::input_cursor(&app, console.width);
+
+    // When stacked keep the console on top so the prompt is never pushed off screen
+    let stacked = console_width == layout.width;
+    let panes = match stacked {
+        true => [console, elements],
+        false => [elements, console],
+    };
+
     let mut stdout = app.stdout.lock();
-    queue!(stdout, Clear(ClearType::All), MoveTo(0, 0)).unwrap();
+    queue!(stdout, Clear(ClearType::All)).unwrap();
+    if let Some(tabs) = tabs {
+        stdout.queue(MoveTo(0, 0)).unwrap();
+        tabs.truncate(layout.width).queue(&mut stdout);
+    }
+    let origins = layout.render(&mut stdout, &panes);
+
+    *app.element_hitboxes.write() = layout.hitboxes(&panes, &origins);
+
+    let (x, y) = origins[!stacked as usize];
+    stdout
+        .queue(MoveTo(x + input_cursor.0, y + input_cursor.1))
+        .unwrap();
+
+    stdout.flush().unwrap();
+}
+
+mod layout {
+    use super::*;
+
+    // Minimum width a pane needs before it gets moved below the previous one
+    const MIN_PANE_WIDTH: usize = 24;
+    const GAP: usize = 1;
+
+    pub struct Pane {
+        pub lines: Lines,
+        pub width: usize,
+        // Lines that represent an element, used for mouse hit testing
+        pub hitboxes: Vec<(usize, ElementIdentifier)>,
+    }
+
+    // A clickable region on screen: row, first column, last column
+    pub type Hitbox = ((u16, u16, u16), ElementIdentifier);
+
+    pub struct Layout {
+        pub width: usize,
+        pub height: usize,
+        // Rows above the panes
+        pub top: usize,
+    }
+
+    impl Layout {
+        pub fn from_terminal() -> Self {
+            let (width, height) = crossterm::terminal::size().unwrap_or((80, 24));
+            Self {
+                width: width as usize,
+                height: height as usize,
+                top: 0,
+            }
+        }
+
+        // Width left for a new pane.
+        // If it wont fit next to the others it gets the full width and is reflowed below them
+        pub fn remaining_width(&self, panes: &[&Pane]) -> usize {
+            let used = panes.iter().map(|x| x.width + GAP).sum::<usize>();
+            match self.width.saturating_sub(used) {
+                x if x >= MIN_PANE_WIDTH => x,
+                _ => self.width,
+            }
+        }
+
+        // Draws the panes left to right, wrapping onto new rows when the terminal is too narrow.
+        // Returns the top left corner of each pane.
+        pub fn render(&self, stdout: &mut StdoutLock, panes: &[Pane]) -> Vec<(u16, u16)> {
+            let mut origins = Vec::new();
+            let (mut x, mut y) = (0, self.top);
+            let mut row_height = 0;
+
+            for pane in panes {
+                if x != 0 && x + pane.width > self.width {
+                    x = 0;
+                    y += row_height + GAP;
+                    row_height = 0;
+                }
+
+                for (i, line) in pane.lines.iter().enumerate() {
+                    if y + i >= self.height {
+                        break;
+                    }
+
+                    stdout.queue(MoveTo(x as u16, (y + i) as u16)).unwrap();
+                    line.truncate(self.width.saturating_sub(x)).queue(stdout);
+                }
+
+                origins.push((x as u16, y as u16));
+                row_height = row_height.max(pane.lines.len());
+                x += pane.width + GAP;
+            }
 
-    let columns = [elements::get(app.clone()), console::get(app.clone())];
-    let max_len = columns.iter().map(|x| x.len()).max().unwrap_or(0);
+            origins
+        }
 
-    for line in 0..max_len {
-        for column in &columns {
-            match column.get(line) {
-                Some(line) => {
-                    line.queue(&mut stdout);
-                    stdout.queue(Print(" ")).unwrap();
+        // Translate pane relative hitboxes to screen positions, skipping any that are off screen
+        pub fn hitboxes(&self, panes: &[Pane], origins: &[(u16, u16)]) -> Vec<Hitbox> {
+            let mut out = Vec::new();
+            for (pane, (x, y)) in panes.iter().zip(origins) {
+                let end = (*x as usize + pane.width).min(self.width) as u16;
+                for (line, id) in pane.hitboxes.iter() {
+                    let row = *y as usize + line;
+                    if row < self.height {
+                        out.push(((row as u16, *x, end.saturating_sub(1)), *id));
+                    }
                 }
-                None => queue!(stdout, Print(" ".repeat(max_len + 1))).unwrap(),
             }
+
+            out
         }
-        queue!(stdout, Print("\n")).unwrap();
     }
-    stdout
-        .queue(MoveTo(columns[0][0].len as u16 + 5, 1))
-        .unwrap();
+}
+
+mod tabs {
+    use crossterm::style::{Attribute, Stylize};
+
+    use super::*;
+
+    // Every game by name with the current one highlighted, None when there is only one
+    pub fn get(app: &App, tables: &[Table]) -> Option<Line> {
+        if tables.len() < 2 {
+            return None;
+        }
+
+        let current = *app.current.read();
+        let theme = app.theme.read();
+        let mut out = Line::from("");
+        for (i, table) in tables.iter().enumerate() {
+            let style = match i == current {
+                true => ContentStyle::new().attribute(Attribute::Reverse),
+                false => ContentStyle::new().with(theme.muted),
+            };
+            out = out.append(Line::from(format!(" {} ", table.name)).styled(style));
+        }
 
-    app.stdout.lock().flush().unwrap();
+        Some(out.append(Line::from(" (game <name>)").styled(ContentStyle::new().with(theme.muted))))
+    }
 }
 
 mod elements {
-    use crossterm::style::{Color, Stylize};
-    use hashbrown::HashMap;
-
-    use crate::{
+    use clue_solver::{
         constraints::SolvedState,
-        element::{ElementIdentifier, ElementType},
+        element::{Element, ElementType},
+        Game,
     };
+    use crossterm::style::{Color, Stylize};
 
     use super::*;
 
-    pub fn get(app: Arc<App>) -> Lines {
-        let app_cache = app.clone();
-        let max_name_length = app.elements.read().max_name_length;
-        let constraints = app_cache.constraint_cache.read();
-        get_draw(app)
+    // Borders, index and the you / solver columns take up 9 characters around the name
+    const CHROME_WIDTH: usize = 9;
+
+    pub fn get(app: &App, game: &Game, max_width: usize) -> layout::Pane {
+        let name_width = game
+            .board()
+            .elements()
+            .max_name_length
+            .min(max_width.saturating_sub(CHROME_WIDTH))
+            .max(1);
+        let constraints = &game.deductions().states;
+        let theme = app.theme.read();
+        let draw = get_draw(game);
+        let hitboxes = draw
+            .iter()
+            .enumerate()
+            .filter_map(|(i, x)| match x {
+                Draw::Element(id, ..) => Some((i, *id)),
+                _ => None,
+            })
+            .collect();
+        let mut lines = draw
             .into_iter()
             .map(|element| match element {
-                Draw::Separator(title) => {
-                    let padding = "-".repeat(max_name_length + 1 - title.len());
-                    format!("+-+-{}{}+", title, padding).into()
-                }
+                Draw::Separator(title) => separator(&title, name_width),
                 Draw::Element(id, name, state) => {
-                    let len = name.len();
+                    let name = truncate(&name, name_width);
+                    let len = name.chars().count();
+                    let conflict = matches!(constraints.get(&id), Some(None));
+                    let deduced = constraints.get(&id).copied().flatten();
+                    let (user_symbol, user_color) = match state {
+                        ElementState::Unknown => (theme.unknown_symbol, theme.muted),
+                        x => (theme.state_symbol(x), theme.state_color(x)),
+                    };
+                    let (solver_symbol, solver_color) = match conflict {
+                        true => (theme.conflict_symbol, theme.error),
+                        false => get_solver_symbol(deduced, &theme),
+                    };
+
                     Line::from("|")
                         .append((id.index + 1).to_string().chars().last().unwrap())
-                        .styled(ContentStyle::new().with(match state {
-                            ElementState::Confirmed => Color::Green,
-                            ElementState::Dismissed => Color::Red,
-                            _ => Color::Reset,
-                        }))
+                        .append("|")
+                        .append(user_symbol)
+                        .styled(ContentStyle::new().with(user_color))
+                        .append(solver_symbol)
+                        .styled(ContentStyle::new().with(solver_color))
                         .append("| ")
                         .append(name)
-                        .styled(ContentStyle::new().with(get_element_color(id, &constraints)))
-                        .append(" ".repeat(max_name_length - len))
+                        .styled(ContentStyle::new().with(get_element_color(state, deduced, &theme)))
+                        .append(" ".repeat(name_width - len))
                         .append(" |")
                 }
             })
+            .collect::<Lines>();
+
+        // Keep the bottom border below the legend
+        let bottom = lines.pop().unwrap();
+        lines.push(separator("Legend", name_width));
+        lines.extend(legend(&theme, name_width + CHROME_WIDTH - 3));
+        lines.push(bottom);
+
+        layout::Pane {
+            lines,
+            width: name_width + CHROME_WIDTH,
+            hitboxes,
+        }
+    }
+
+    fn separator(title: &str, name_width: usize) -> Line {
+        let title = truncate(title, name_width + 1);
+        let padding = "-".repeat(name_width + 1 - title.chars().count());
+        format!("+-+--+-{}{}+", title, padding).into()
+    }
+
+    // What the two marker columns mean
+    fn legend(theme: &Theme, width: usize) -> Lines {
+        let entries = [
+            (
+                [theme.confirmed_symbol, theme.dismissed_symbol],
+                [theme.confirmed, theme.dismissed],
+                "you marked",
+            ),
+            (
+                [theme.confirmed_symbol, theme.dismissed_symbol],
+                [theme.deduced_confirmed, theme.deduced_dismissed],
+                "solver deduced",
+            ),
+            (
+                [theme.any_symbol, ' '],
+                [theme.muted, theme.muted],
+                "either way",
+            ),
+            (
+                [theme.conflict_symbol, ' '],
+                [theme.error, theme.error],
+                "conflicting",
+            ),
+        ];
+
+        entries
+            .into_iter()
+            .map(|(symbols, colors, text)| {
+                let line = Line::from(symbols[0])
+                    .styled(ContentStyle::new().with(colors[0]))
+                    .append(symbols[1])
+                    .styled(ContentStyle::new().with(colors[1]))
+                    .append(format!(" {}", text))
+                    .truncate(width);
+                Line::from("| ")
+                    .append_line(&line)
+                    .append(" ".repeat(width - line.len))
+                    .append("|")
+            })
             .collect()
     }
 
+    // Names take the colour of what the user entered, falling back to what the solver found
     fn get_element_color(
-        id: ElementIdentifier,
-        constraints: &HashMap<ElementIdentifier, Option<SolvedState>>,
+        state: ElementState,
+        deduced: Option<SolvedState>,
+        theme: &Theme,
     ) -> Color {
-        match constraints.get(&id) {
-            Some(Some(SolvedState::Confirmed)) => Color::Green,
-            Some(Some(SolvedState::Dismissed)) => Color::Red,
-            _ => Color::Reset,
+        match (state, deduced) {
+            (ElementState::Unknown, Some(SolvedState::Confirmed)) => theme.deduced_confirmed,
+            (ElementState::Unknown, Some(SolvedState::Dismissed)) => theme.deduced_dismissed,
+            (x, _) => theme.state_color(x),
         }
     }
 
-    fn get_draw(app: Arc<App>) -> Vec<Draw> {
+    fn get_solver_symbol(deduced: Option<SolvedState>, theme: &Theme) -> (char, Color) {
+        match deduced {
+            Some(SolvedState::Confirmed) => (theme.confirmed_symbol, theme.deduced_confirmed),
+            Some(SolvedState::Dismissed) => (theme.dismissed_symbol, theme.deduced_dismissed),
+            Some(SolvedState::Any) => (theme.any_symbol, theme.muted),
+            None => (' ', theme.muted),
+        }
+    }
+
+    fn get_draw(game: &Game) -> Vec<Draw> {
         let mut out = Vec::new();
-        let card = app.elements.read();
+        let card = game.board().elements();
 
         // Todo: Replace this with iterator magic
         out.push(Draw::Separator("(L)ocations".to_string()));
@@ -107,7 +348,7 @@ mod elements {
                     index: i,
                 },
                 element.name.clone(),
-                element.state,
+                user_state(element),
             ));
         }
         out.push(Draw::Separator("(P)eople".to_string()));
@@ -118,7 +359,7 @@ mod elements {
                     index: i,
                 },
                 element.name.clone(),
-                element.state,
+                user_state(element),
             ));
         }
         out.push(Draw::Separator("(W)eapons".to_string()));
@@ -129,7 +370,7 @@ mod elements {
                     index: i,
                 },
                 element.name.clone(),
-                element.state,
+                user_state(element),
             ));
         }
         out.push(Draw::Separator("".to_string()));
@@ -137,105 +378,451 @@ mod elements {
         out
     }
 
+    // Derived states are shown in the solver column, not as if the user entered them
+    fn user_state(element: &Element) -> ElementState {
+        match element.derived {
+            true => ElementState::Unknown,
+            false => element.state,
+        }
+    }
+
     enum Draw {
         Separator(String),
         Element(ElementIdentifier, String, ElementState),
     }
 }
 
+mod grid {
+    use clue_solver::{
+        constraints::SolvedState,
+        element::{ElementType, Knowledge},
+        Game,
+    };
+    use crossterm::style::Stylize;
+
+    use super::*;
+
+    // Index column and borders around the name
+    const CHROME_WIDTH: usize = 6;
+    // Each player (and the envelope) gets a 3 wide column plus a separator
+    const COLUMN_WIDTH: usize = 4;
+
+    pub fn get(app: &App, game: &Game, max_width: usize) -> layout::Pane {
+        let elements = game.board().elements();
+        let cache = &game.deductions().states;
+        let holders = &game.deductions().holders;
+        let theme = app.theme.read();
+        let columns = elements.players.len() + 1;
+        let name_width = elements
+            .max_name_length
+            .min(max_width.saturating_sub(CHROME_WIDTH + columns * COLUMN_WIDTH))
+            .max(1);
+        let cells_width = columns * COLUMN_WIDTH;
+
+        let mut header = Line::from(format!("| |{}|", " ".repeat(name_width + 2)))
+            .append("Env")
+            .styled(ContentStyle::new().with(theme.muted));
+        for (i, player) in elements.players.iter().enumerate() {
+            // Kept to the column width once the player number has two digits
+            let label = format!("{}{}", i + 1, player)
+                .chars()
+                .take(3)
+                .collect::<String>();
+            header = header.append("|").append(format!("{:<3}", label));
+        }
+        header = header.append("|");
+
+        let mut lines = vec![
+            format!(
+                "+-+-Notepad{}+",
+                "-".repeat((name_width + cells_width).saturating_sub(6))
+            )
+            .into(),
+            header,
+        ];
+        let mut hitboxes = Vec::new();
+
+        let sections = [
+            ("(L)ocations", ElementType::Location, &elements.locations),
+            ("(P)eople", ElementType::Person, &elements.people),
+            ("(W)eapons", ElementType::Weapon, &elements.weapons),
+        ];
+        for (title, element_type, list) in sections {
+            let title = truncate(title, name_width + 1);
+            lines.push(
+                format!(
+                    "+-+-{}{}+{}+",
+                    title,
+                    "-".repeat(name_width + 1 - title.chars().count()),
+                    "-".repeat(cells_width - 1)
+                )
+                .into(),
+            );
+
+            for (index, element) in list.iter().enumerate() {
+                let id = ElementIdentifier {
+                    element_type,
+                    index,
+                };
+                let name = truncate(&element.name, name_width);
+
+                let envelope = match elements.envelope_knowledge(&id) {
+                    Knowledge::Unknown => match cache.get(&id) {
+                        Some(Some(SolvedState::Confirmed)) => Knowledge::Deduced(true),
+                        Some(Some(SolvedState::Dismissed)) => Knowledge::Deduced(false),
+                        _ => Knowledge::Unknown,
+                    },
+                    x => x,
+                };
+
+                let mut line = Line::from(format!(
+                    "|{}| {}{} |",
+                    (index + 1).to_string().chars().last().unwrap(),
+                    name,
+                    " ".repeat(name_width - name.chars().count())
+                ));
+                line = cell(line, envelope, &theme);
+                for player in 0..elements.players.len() {
+                    let holds = match elements.player_knowledge(&id, player) {
+                        Knowledge::Unknown => match holders.get(&(id, player)) {
+                            Some(Some(x)) => Knowledge::Deduced(*x),
+                            // Someone else was found to hold it
+                            _ if (0..elements.players.len())
+                                .any(|x| holders.get(&(id, x)) == Some(&Some(true))) =>
+                            {
+                                Knowledge::Deduced(false)
+                            }
+                            _ => Knowledge::Unknown,
+                        },
+                        x => x,
+                    };
+                    line = cell(line.append("|"), holds, &theme);
+                }
+
+                hitboxes.push((lines.len(), id));
+                lines.push(line.append("|"));
+            }
+        }
+        lines.push(
+            format!(
+                "+-+{}+{}+",
+                "-".repeat(name_width + 2),
+                "-".repeat(cells_width - 1)
+            )
+            .into(),
+        );
+
+        layout::Pane {
+            lines,
+            width: name_width + CHROME_WIDTH + cells_width,
+            hitboxes,
+        }
+    }
+
+    fn cell(line: Line, knowledge: Knowledge, theme: &Theme) -> Line {
+        let (symbol, color) = match knowledge {
+            Knowledge::Unknown => ('·', theme.muted),
+            Knowledge::Known(true) => (theme.confirmed_symbol, theme.confirmed),
+            Knowledge::Known(false) => (theme.dismissed_symbol, theme.dismissed),
+            Knowledge::Deduced(true) => (theme.confirmed_symbol, theme.deduced_confirmed),
+            Knowledge::Deduced(false) => (theme.dismissed_symbol, theme.deduced_dismissed),
+        };
+
+        line.append(format!(" {} ", symbol))
+            .styled(ContentStyle::new().with(color))
+    }
+}
+
 mod console {
-    use crossterm::style::{Attribute, Color, Stylize};
+    use clue_solver::constraints::SolvedState;
+    use crossterm::style::{Attribute, Stylize};
 
     use super::*;
 
-    pub fn get(app: Arc<App>) -> Lines {
-        let mut lines = app
-            .command_history
-            .read()
+    // Space taken by the "| " and " |" borders
+    const BORDER_WIDTH: usize = 4;
+    const MIN_WIDTH: usize = 20;
+
+    pub fn get(app: &App, table: &Table, max_width: usize) -> layout::Pane {
+        let inner_width = max_width.saturating_sub(BORDER_WIDTH).max(MIN_WIDTH);
+        let theme = app.theme.read();
+        // The timeline shows the commands that led to the state on screen
+        let history = match &table.timeline {
+            Some((_, game)) => game.history(),
+            None => &table.command_history,
+        };
+        let mut lines = history
             .iter()
             .rev()
             .take(3)
             .map(|x| {
+                let (symbol, color) = match x.1 {
+                    Some(_) => (theme.dismissed_symbol, theme.error),
+                    None => (theme.confirmed_symbol, theme.ok),
+                };
                 Line::from(format!("{}: ", x.0))
-                    .append(x.1.clone().unwrap_or_else(|| "ok".to_owned()))
-                    .styled(
-                        ContentStyle::new()
-                            .attribute(Attribute::Bold)
-                            .with(match x.1 {
-                                Some(_) => Color::Red,
-                                None => Color::Green,
-                            }),
-                    )
+                    .append(format!(
+                        "{} {}",
+                        symbol,
+                        x.1.clone().unwrap_or_else(|| "ok".to_owned())
+                    ))
+                    .styled(ContentStyle::new().attribute(Attribute::Bold).with(color))
             })
             .collect::<Vec<Line>>();
 
-        if app.command_history.read().len() > 3 {
-            lines.push(Line::from("...").styled(ContentStyle::new().with(Color::DarkGrey)));
+        if history.len() > 3 {
+            lines.push(Line::from("...").styled(ContentStyle::new().with(theme.muted)));
         }
 
-        let max_len = lines.iter().map(|x| x.len).max().unwrap_or(0).max(20);
+        let game = table.shown();
+        let elements = game.board().elements();
+        for (id, found) in game.deductions().conflicts.iter() {
+            let name = elements.get(id).map(|x| x.name.as_str()).unwrap_or("?");
+            let sources = found
+                .iter()
+                .filter_map(|(i, state)| {
+                    let symbol = match state {
+                        SolvedState::Confirmed => theme.confirmed_symbol,
+                        SolvedState::Dismissed => theme.dismissed_symbol,
+                        SolvedState::Any => return None,
+                    };
+                    Some(format!("#{} {}", i + 1, symbol))
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            lines.push(
+                Line::from(format!(
+                    "{} Conflict on {} ({}): {}",
+                    theme.conflict_symbol, name, id, sources
+                ))
+                .styled(
+                    ContentStyle::new()
+                        .attribute(Attribute::Bold)
+                        .with(theme.error),
+                ),
+            );
+        }
+
+        let constraints = constraints::get(app, table, max_width);
+        let max_len = lines
+            .iter()
+            .map(|x| x.len)
+            .max()
+            .unwrap_or(0)
+            .max(MIN_WIDTH)
+            .max(constraints.width.saturating_sub(BORDER_WIDTH))
+            .min(inner_width);
         lines.iter_mut().for_each(|x| {
+            let line = x.truncate(max_len);
             *x = Line::from("| ")
-                .append_line(x)
-                .append(" ".repeat(max_len - x.len))
+                .append_line(&line)
+                .append(" ".repeat(max_len - line.len))
                 .append(" |")
         });
 
-        lines.insert(0, format!("| >{}|", " ".repeat(max_len)).into());
+        if let Some(marker) = error_marker(app, max_len, &theme) {
+            lines.insert(0, marker);
+        }
+
+        let input = visible_input(app, max_len);
+        lines.insert(
+            0,
+            format!(
+                "| >{}{}|",
+                input,
+                " ".repeat(max_len - input.chars().count())
+            )
+            .into(),
+        );
+        let title = match game.editing() {
+            _ if table.timeline.is_some() => format!(
+                "Timeline-{}/{}-(←→)-(esc)",
+                game.history().len(),
+                table.game.history().len()
+            ),
+            Some(x) => format!("Editing-#{}-(esc)", x + 1),
+            None if game.auto_apply() => "Console-(cdu)-auto".to_owned(),
+            None => "Console-(cdu)".to_owned(),
+        };
+        let title = truncate(&title, max_len + 1);
         lines.insert(
             0,
             Line::from("+-")
-                .append("Console-(cdu)")
-                .append("-".repeat(max_len - 12))
+                .append(&title)
+                .append("-".repeat(max_len + 1 - title.chars().count()))
                 .append("+"),
         );
         lines.push(format!("+{}+", "-".repeat(max_len + 2)).into());
 
         lines.push(String::new().into());
-        lines.extend(constraints::get(app));
-        lines
+        lines.extend(constraints.lines);
+
+        layout::Pane {
+            lines,
+            width: max_len + BORDER_WIDTH,
+            hitboxes: Vec::new(),
+        }
+    }
+
+    // Carets under the part of the input that failed to parse
+    fn error_marker(app: &App, width: usize, theme: &Theme) -> Option<Line> {
+        let (span, message) = app.input_error.read().clone()?;
+        let len = app.input.read().chars().count();
+        let skipped = (len + 1).saturating_sub(width);
+        let start = span.start.saturating_sub(skipped);
+        let carets = span
+            .end
+            .saturating_sub(skipped)
+            .saturating_sub(start)
+            .max(1);
+
+        let marker = Line::from(format!(
+            " {}{} {}",
+            " ".repeat(start),
+            "^".repeat(carets),
+            message
+        ))
+        .styled(ContentStyle::new().with(theme.error))
+        .truncate(width);
+        Some(
+            Line::from("| ")
+                .append_line(&marker)
+                .append(" ".repeat(width - marker.len))
+                .append(" |"),
+        )
+    }
+
+    // Position of the cursor in the input line, relative to the console pane
+    pub fn input_cursor(app: &App, width: usize) -> (u16, u16) {
+        let input = visible_input(app, width - BORDER_WIDTH);
+        (3 + input.chars().count() as u16, 1)
+    }
+
+    // The tail end of the input that fits in the prompt
+    fn visible_input(app: &App, width: usize) -> String {
+        let input = app.input.read();
+        let len = input.chars().count();
+        input
+            .chars()
+            .skip((len + 1).saturating_sub(width))
+            .collect()
     }
 }
 
 mod constraints {
-    use crossterm::style::{Color, Stylize};
+    use clue_solver::constraints::{simplify::Simplified, Constraint, SolvedState};
+    use crossterm::style::{Attribute, Stylize};
 
     use super::*;
 
-    pub fn get(app: Arc<App>) -> Lines {
-        let unsolved = app.unsolved_constraints.read();
-
-        let mut lines = app
-            .constraints
-            .read()
+    pub fn get(app: &App, table: &Table, max_width: usize) -> layout::Pane {
+        let game = table.shown();
+        let deductions = game.deductions();
+        let (unsolved, solved, conflicts) = (
+            &deductions.unsolved,
+            &deductions.solved,
+            &deductions.conflicts,
+        );
+        let elements = game.board().elements();
+        let selected = table.selected_constraint;
+        let simplified = game.simplified();
+        let theme = app.theme.read();
+        let inner_width = max_width.saturating_sub(4).max(20);
+
+        let constraints = game.board().constraints();
+        let number_width = constraints.len().to_string().len();
+        let mut lines = constraints
             .iter()
-            .rev()
-            .map(|x| {
-                Line::from(x.to_string()).styled(ContentStyle::new().with(
-                    if unsolved.contains(x) {
-                        Color::DarkGrey
-                    } else {
-                        Color::Reset
-                    },
-                ))
+            .enumerate()
+            .map(|(i, x)| {
+                let mut style = ContentStyle::new().with(if unsolved.contains(x) {
+                    theme.muted
+                } else {
+                    theme.unknown
+                });
+                if selected == Some(i) {
+                    style = style.attribute(Attribute::Reverse);
+                }
+
+                let mut line = Line::from(format!("{:>1$} ", i + 1, number_width))
+                    .styled(ContentStyle::new().with(theme.muted))
+                    .append(x.to_string())
+                    .styled(style);
+
+                if let Some(simplified) = simplified.get(i) {
+                    line = append_simplified(line, x, simplified, &theme);
+                }
+
+                if let Some((id, state)) = solved.get(x) {
+                    let name = elements.get(id).map(|x| x.name.as_str()).unwrap_or("?");
+                    let conflicting = conflicts
+                        .get(id)
+                        .map(|x| x.iter().any(|x| x.0 == i))
+                        .unwrap_or(false);
+                    line = line.append(" => ").append(format!("{} ({})", name, id));
+                    line = match conflicting {
+                        true => line
+                            .append(format!(" {}", theme.conflict_symbol))
+                            .styled(ContentStyle::new().with(theme.error)),
+                        false => line.styled(ContentStyle::new().with(match state {
+                            SolvedState::Confirmed => theme.deduced_confirmed,
+                            SolvedState::Dismissed => theme.deduced_dismissed,
+                            SolvedState::Any => theme.muted,
+                        })),
+                    };
+                }
+
+                line
             })
             .collect::<Vec<Line>>();
 
-        let max_len = lines.iter().map(|x| x.len).max().unwrap_or(0).max(20);
+        let max_len = lines
+            .iter()
+            .map(|x| x.len)
+            .max()
+            .unwrap_or(0)
+            .max(20)
+            .min(inner_width);
         lines.iter_mut().for_each(|x| {
+            let line = x.truncate(max_len);
             *x = Line::from("| ")
-                .append_line(x)
-                .append(" ".repeat(max_len - x.len))
+                .append_line(&line)
+                .append(" ".repeat(max_len - line.len))
                 .append(" |")
         });
 
         lines.insert(
             0,
-            format!("+-Constraints{}+", "-".repeat(max_len - 10)).into(),
+            format!("+-Constraints-(↑↓){}+", "-".repeat(max_len - 15)).into(),
         );
         lines.push(format!("+{}+", "-".repeat(max_len + 2)).into());
 
-        lines
+        layout::Pane {
+            lines,
+            width: max_len + 4,
+            hitboxes: Vec::new(),
+        }
+    }
+
+    // Show what the constraint reduces to given what is already known
+    fn append_simplified(
+        line: Line,
+        constraint: &Constraint,
+        simplified: &Simplified,
+        theme: &Theme,
+    ) -> Line {
+        let (text, color) = match simplified {
+            Simplified::Satisfied => (" (satisfied)".to_owned(), theme.muted),
+            Simplified::Violated => (" (violated)".to_owned(), theme.error),
+            Simplified::Subsumed(x) => (format!(" (redundant, see #{})", x + 1), theme.muted),
+            Simplified::Reduced(x) if Some(x) != constraint.clause().as_ref() => {
+                let reduced = x.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+                (format!(" ~ {}", reduced.join(" | ")), theme.muted)
+            }
+            _ => return line,
+        };
+
+        line.append(text).styled(ContentStyle::new().with(color))
     }
 }
 
@@ -273,6 +860,33 @@ impl Line {
         }
     }
 
+    // Cut the line down to `width` characters, keeping styles and marking the cut with a '…'
+    fn truncate(&self, width: usize) -> Self {
+        if self.len <= width {
+            return Self {
+                elements: self.elements.clone(),
+                len: self.len,
+            };
+        }
+
+        let mut elements = Vec::new();
+        let mut left = width.saturating_sub(1);
+        for element in self.elements.iter() {
+            let content = element.content().chars().take(left).collect::<String>();
+            left -= content.chars().count();
+            elements.push(StyledContent::new(*element.style(), content));
+        }
+
+        if width > 0 {
+            elements.push(StyledContent::new(ContentStyle::default(), "…".to_owned()));
+        }
+
+        Self {
+            elements,
+            len: width,
+        }
+    }
+
     fn queue(&self, stdout: &mut StdoutLock) {
         for element in self.elements.iter() {
             queue!(stdout, PrintStyledContent(element.clone())).unwrap();
@@ -280,12 +894,26 @@ impl Line {
     }
 }
 
+// Shorten a string to `width` characters, marking the cut with a '…'
+fn truncate(inp: &str, width: usize) -> String {
+    if inp.chars().count() <= width {
+        return inp.to_owned();
+    }
+
+    let mut out = inp
+        .chars()
+        .take(width.saturating_sub(1))
+        .collect::<String>();
+    out.push('…');
+    out
+}
+
 impl<T: Display> From<T> for Line {
     fn from(inp: T) -> Self {
         let inp = inp.to_string();
 
         Self {
-            len: inp.len(),
+            len: inp.chars().count(),
             elements: vec![StyledContent::new(ContentStyle::default(), inp)],
         }
     }
@@ -0,0 +1,147 @@
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::Arc,
+};
+
+use clue_solver::{element::Elements, server, Board, Game};
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use tungstenite::Message;
+
+fn start() -> (SocketAddr, Arc<server::Clients>) {
+    let board = Board::new(Elements::numbered(6));
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let clients = server::spawn(listener, Arc::new(Mutex::new(Game::new(board))));
+    (addr, clients)
+}
+
+// Send a raw request and split the response into status line and body
+fn http(addr: SocketAddr, request: &str) -> (String, String) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(request.as_bytes()).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    (head.lines().next().unwrap().to_owned(), body.to_owned())
+}
+
+fn post(addr: SocketAddr, body: &Value) -> Value {
+    let body = body.to_string();
+    let (status, body) = http(
+        addr,
+        &format!(
+            "POST /request HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        ),
+    );
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    serde_json::from_str(&body).unwrap()
+}
+
+fn state_of(state: &Value, id: &str) -> Value {
+    state["elements"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|x| x["id"] == id)
+        .unwrap()["state"]
+        .clone()
+}
+
+#[test]
+fn get_state() {
+    let (addr, _) = start();
+    let (status, body) = http(addr, "GET /state HTTP/1.1\r\n\r\n");
+    assert_eq!(status, "HTTP/1.1 200 OK");
+
+    let state = serde_json::from_str::<Value>(&body).unwrap();
+    assert_eq!(state["elements"].as_array().unwrap().len(), 18);
+    assert_eq!(state_of(&state, "w2"), "unknown");
+
+    let (status, _) = http(addr, "GET /nothing HTTP/1.1\r\n\r\n");
+    assert_eq!(status, "HTTP/1.1 404 Not Found");
+}
+
+#[test]
+fn websocket_gets_changes() {
+    let (addr, clients) = start();
+    let stream = TcpStream::connect(addr).unwrap();
+    let (mut socket, _) = tungstenite::client(format!("ws://{}/ws", addr), stream).unwrap();
+
+    let mut next = || match socket.read().unwrap() {
+        Message::Text(x) => serde_json::from_str::<Value>(&x).unwrap(),
+        x => panic!("Unexpected message {:?}", x),
+    };
+
+    let first = next();
+    assert_eq!(first["event"], "changed");
+    assert_eq!(state_of(&first["state"], "w2"), "unknown");
+
+    // A command from someone else is pushed to this socket
+    let response = post(
+        addr,
+        &json!({ "id": 1, "type": "command", "command": "w2d" }),
+    );
+    assert_eq!(
+        response,
+        json!({ "id": 1, "ok": true, "result": { "kind": "done" } })
+    );
+    let pushed = next();
+    assert_eq!(pushed["event"], "changed");
+    assert_eq!(state_of(&pushed["state"], "w2"), "dismissed");
+
+    // So are changes made by whatever is hosting the game
+    clients.changed(json!({ "from": "host" }));
+    assert_eq!(
+        next(),
+        json!({ "event": "changed", "state": { "from": "host" } })
+    );
+}
+
+#[test]
+fn large_bodies_are_refused() {
+    let (addr, _) = start();
+    let (status, _) = http(
+        addr,
+        "POST /request HTTP/1.1\r\nContent-Length: 1000000000\r\n\r\n",
+    );
+    assert_eq!(status, "HTTP/1.1 413 Payload Too Large");
+}
+
+#[test]
+fn large_headers_are_refused() {
+    let (addr, _) = start();
+    // Exactly the 8 KiB the server reads, so nothing is left unread when it answers
+    let head = format!("GET / HTTP/1.1\r\nX-Padding: {}", "a".repeat(8 * 1024));
+    let (status, _) = http(addr, &head[..8 * 1024]);
+    assert_eq!(status, "HTTP/1.1 431 Request Header Fields Too Large");
+}
+
+#[test]
+fn edits_cannot_start_remotely() {
+    let (addr, _) = start();
+    post(
+        addr,
+        &json!({ "id": 1, "type": "command", "command": "w1 | w2" }),
+    );
+    let response = post(
+        addr,
+        &json!({ "id": 2, "type": "command", "command": "edit 1" }),
+    );
+    assert_eq!(response["ok"], false);
+
+    // So the next command is not taken as the new constraint
+    post(
+        addr,
+        &json!({ "id": 3, "type": "command", "command": "w3c" }),
+    );
+    let (_, body) = http(addr, "GET /state HTTP/1.1\r\n\r\n");
+    let state = serde_json::from_str::<Value>(&body).unwrap();
+    assert_eq!(state["constraints"].as_array().unwrap().len(), 1);
+    assert_eq!(state_of(&state, "w3"), "confirmed");
+}